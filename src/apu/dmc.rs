@@ -0,0 +1,190 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct DMC {
+    pub irq_enabled: bool,
+    pub irq_flag: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+
+    sample_addr: u16,
+    sample_length: u16,
+    current_addr: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl DMC {
+    pub fn new() -> Self {
+        DMC {
+            irq_enabled: false,
+            irq_flag: false,
+            looping: false,
+            timer_period: RATE_TABLE[0],
+            timer: RATE_TABLE[0],
+            output_level: 0,
+
+            sample_addr: 0xC000,
+            sample_length: 1,
+            current_addr: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    // $4010: IL-- RRRR
+    pub fn write_control(&mut self, data: u8) {
+        self.irq_enabled = data & 0b1000_0000 != 0;
+        self.looping = data & 0b0100_0000 != 0;
+        self.timer_period = RATE_TABLE[(data & 0b1111) as usize];
+        if !self.irq_enabled {
+            self.irq_flag = false;
+        }
+    }
+
+    // $4011: -DDD DDDD
+    pub fn write_direct_load(&mut self, data: u8) {
+        self.output_level = data & 0b0111_1111;
+    }
+
+    // $4012: sample address = %11AAAAAA.AA000000
+    pub fn write_sample_addr(&mut self, data: u8) {
+        self.sample_addr = 0xC000 | ((data as u16) << 6);
+    }
+
+    // $4013: sample length = %LLLL.LLLL0001
+    pub fn write_sample_length(&mut self, data: u8) {
+        self.sample_length = ((data as u16) << 4) | 1;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Address the memory reader wants to fetch next, if the sample buffer is empty.
+    pub fn pending_fetch(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_addr)
+        } else {
+            None
+        }
+    }
+
+    pub fn fill_sample_buffer(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        // the address wraps around to $8000, not $0000
+        self.current_addr = self.current_addr.wrapping_add(1) | 0x8000;
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle; the rate table is expressed in CPU cycles.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+impl Snapshot for DMC {
+    fn save(&self, state: &mut StateWriter) {
+        state.bool(self.irq_enabled);
+        state.bool(self.irq_flag);
+        state.bool(self.looping);
+        state.u16(self.timer_period);
+        state.u16(self.timer);
+        state.u8(self.output_level);
+        state.u16(self.sample_addr);
+        state.u16(self.sample_length);
+        state.u16(self.current_addr);
+        state.u16(self.bytes_remaining);
+        state.bool(self.sample_buffer.is_some());
+        state.u8(self.sample_buffer.unwrap_or(0));
+        state.u8(self.shift_register);
+        state.u8(self.bits_remaining);
+        state.bool(self.silence);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.irq_enabled = state.bool();
+        self.irq_flag = state.bool();
+        self.looping = state.bool();
+        self.timer_period = state.u16();
+        self.timer = state.u16();
+        self.output_level = state.u8();
+        self.sample_addr = state.u16();
+        self.sample_length = state.u16();
+        self.current_addr = state.u16();
+        self.bytes_remaining = state.u16();
+        let buffered = state.bool();
+        let sample = state.u8();
+        self.sample_buffer = if buffered { Some(sample) } else { None };
+        self.shift_register = state.u8();
+        self.bits_remaining = state.u8();
+        self.silence = state.bool();
+    }
+}
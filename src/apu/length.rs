@@ -0,0 +1,60 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+pub struct LengthCounter {
+    pub counter: u8,
+    pub halt: bool,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new() -> Self {
+        LengthCounter {
+            counter: 0,
+            halt: false,
+            enabled: false,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    // the counter is only reloaded while the channel is enabled in $4015
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+impl Snapshot for LengthCounter {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.counter);
+        state.bool(self.halt);
+        state.bool(self.enabled);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.counter = state.u8();
+        self.halt = state.bool();
+        self.enabled = state.bool();
+    }
+}
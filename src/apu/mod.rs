@@ -0,0 +1,355 @@
+mod dmc;
+mod envelope;
+mod length;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use dmc::DMC;
+use noise::Noise;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+
+const CPU_CLOCK_NTSC: f64 = 1_789_773.0;
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// Receives mixed samples (in the 0.0..=1.0 range) at the configured sample rate.
+pub trait AudioSink {
+    fn push_sample(&mut self, sample: f32);
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SequencerMode {
+    FourStep,
+    FiveStep,
+}
+
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: DMC,
+
+    mode: SequencerMode,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u16,
+    frame_reset_delay: u8,
+    cycles: usize,
+
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+    cycles_per_sample: f64,
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    sink: Option<Box<dyn AudioSink>>,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        let mut pulse_table = [0.0; 31];
+        for (n, entry) in pulse_table.iter_mut().enumerate().skip(1) {
+            *entry = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        let mut tnd_table = [0.0; 203];
+        for (n, entry) in tnd_table.iter_mut().enumerate().skip(1) {
+            *entry = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        APU {
+            pulse1: Pulse::new(PulseChannel::One),
+            pulse2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: DMC::new(),
+
+            mode: SequencerMode::FourStep,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            frame_reset_delay: 0,
+            cycles: 0,
+
+            pulse_table,
+            tnd_table,
+            cycles_per_sample: CPU_CLOCK_NTSC / DEFAULT_SAMPLE_RATE as f64,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            sink: None,
+        }
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.sink = Some(sink);
+        self.cycles_per_sample = CPU_CLOCK_NTSC / sample_rate as f64;
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000 => self.pulse1.write_control(data),
+            0x4001 => self.pulse1.write_sweep(data),
+            0x4002 => self.pulse1.write_timer_lo(data),
+            0x4003 => self.pulse1.write_timer_hi(data),
+
+            0x4004 => self.pulse2.write_control(data),
+            0x4005 => self.pulse2.write_sweep(data),
+            0x4006 => self.pulse2.write_timer_lo(data),
+            0x4007 => self.pulse2.write_timer_hi(data),
+
+            0x4008 => self.triangle.write_linear_counter(data),
+            0x400A => self.triangle.write_timer_lo(data),
+            0x400B => self.triangle.write_timer_hi(data),
+
+            0x400C => self.noise.write_control(data),
+            0x400E => self.noise.write_period(data),
+            0x400F => self.noise.write_length(data),
+
+            0x4010 => self.dmc.write_control(data),
+            0x4011 => self.dmc.write_direct_load(data),
+            0x4012 => self.dmc.write_sample_addr(data),
+            0x4013 => self.dmc.write_sample_length(data),
+
+            0x4015 => self.write_status(data),
+            0x4017 => self.write_frame_counter(data),
+
+            // $4009 and $400D are unused
+            _ => {}
+        }
+    }
+
+    fn write_status(&mut self, data: u8) {
+        self.pulse1.length.set_enabled(data & 0b0000_0001 != 0);
+        self.pulse2.length.set_enabled(data & 0b0000_0010 != 0);
+        self.triangle.length.set_enabled(data & 0b0000_0100 != 0);
+        self.noise.length.set_enabled(data & 0b0000_1000 != 0);
+        self.dmc.set_enabled(data & 0b0001_0000 != 0);
+    }
+
+    fn write_frame_counter(&mut self, data: u8) {
+        self.mode = if data & 0b1000_0000 != 0 {
+            SequencerMode::FiveStep
+        } else {
+            SequencerMode::FourStep
+        };
+        self.irq_inhibit = data & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.frame_irq = false;
+        }
+
+        // the sequencer restarts 3 CPU cycles after a write on an APU cycle, 4 otherwise
+        self.frame_reset_delay = if self.cycles & 1 == 0 { 3 } else { 4 };
+    }
+
+    /// $4015 read: IF-D NT21. Reading clears the frame interrupt flag.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    /// $4015 as `read_status` would report it, without acknowledging the frame IRQ.
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length.is_active() {
+            status |= 0b0000_0001;
+        }
+        if self.pulse2.length.is_active() {
+            status |= 0b0000_0010;
+        }
+        if self.triangle.length.is_active() {
+            status |= 0b0000_0100;
+        }
+        if self.noise.length.is_active() {
+            status |= 0b0000_1000;
+        }
+        if self.dmc.is_active() {
+            status |= 0b0001_0000;
+        }
+        if self.frame_irq {
+            status |= 0b0100_0000;
+        }
+        if self.dmc.irq_flag {
+            status |= 0b1000_0000;
+        }
+        status
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_irq_pending() || self.dmc_irq_pending()
+    }
+
+    pub fn frame_irq_pending(&self) -> bool {
+        self.frame_irq
+    }
+
+    pub fn dmc_irq_pending(&self) -> bool {
+        self.dmc.irq_flag
+    }
+
+    pub fn sequencer_mode(&self) -> SequencerMode {
+        self.mode
+    }
+
+    /// Address the DMC memory reader needs, if any. The bus services it with
+    /// `fill_dmc_sample` and stalls the CPU for the fetch.
+    pub fn dmc_fetch_request(&self) -> Option<u16> {
+        self.dmc.pending_fetch()
+    }
+
+    pub fn fill_dmc_sample(&mut self, data: u8) {
+        self.dmc.fill_sample_buffer(data);
+    }
+
+    /// Advances the APU by one CPU cycle.
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycles & 1 == 0 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+
+        self.tick_frame_sequencer();
+        self.tick_sample();
+    }
+
+    fn tick_frame_sequencer(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+                if self.mode == SequencerMode::FiveStep {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+                return;
+            }
+        }
+
+        self.frame_cycle += 1;
+        match (self.mode, self.frame_cycle) {
+            (_, 7457) | (_, 22371) => self.clock_quarter_frame(),
+            (_, 14913) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (SequencerMode::FourStep, 29828) => self.set_frame_irq(),
+            (SequencerMode::FourStep, 29829) => {
+                self.set_frame_irq();
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (SequencerMode::FourStep, 29830) => {
+                self.set_frame_irq();
+                self.frame_cycle = 0;
+            }
+            (SequencerMode::FiveStep, 37281) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (SequencerMode::FiveStep, 37282) => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    // envelopes and the triangle's linear counter
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    // length counters and sweep units
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    /// Non-linear mix of all five channels, see https://www.nesdev.org/wiki/APU_Mixer
+    pub fn output(&self) -> f32 {
+        let pulse = self.pulse1.output() + self.pulse2.output();
+        let tnd = 3 * self.triangle.output() as usize
+            + 2 * self.noise.output() as usize
+            + self.dmc.output() as usize;
+        self.pulse_table[pulse as usize] + self.tnd_table[tnd]
+    }
+
+    fn tick_sample(&mut self) {
+        if self.sink.is_none() {
+            return;
+        }
+
+        self.sample_sum += self.output();
+        self.sample_count += 1;
+        self.sample_clock += 1.0;
+
+        if self.sample_clock >= self.cycles_per_sample {
+            self.sample_clock -= self.cycles_per_sample;
+            let sample = self.sample_sum / self.sample_count as f32;
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+            if let Some(sink) = self.sink.as_mut() {
+                sink.push_sample(sample);
+            }
+        }
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
+
+/// The resampler position is left out, it only shifts where audio samples are cut.
+impl Snapshot for APU {
+    fn save(&self, state: &mut StateWriter) {
+        self.pulse1.save(state);
+        self.pulse2.save(state);
+        self.triangle.save(state);
+        self.noise.save(state);
+        self.dmc.save(state);
+        state.bool(self.mode == SequencerMode::FiveStep);
+        state.bool(self.irq_inhibit);
+        state.bool(self.frame_irq);
+        state.u16(self.frame_cycle);
+        state.u8(self.frame_reset_delay);
+        state.usize(self.cycles);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.pulse1.load(state);
+        self.pulse2.load(state);
+        self.triangle.load(state);
+        self.noise.load(state);
+        self.dmc.load(state);
+        self.mode = if state.bool() {
+            SequencerMode::FiveStep
+        } else {
+            SequencerMode::FourStep
+        };
+        self.irq_inhibit = state.bool();
+        self.frame_irq = state.bool();
+        self.frame_cycle = state.u16();
+        self.frame_reset_delay = state.u8();
+        self.cycles = state.usize();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length::LengthCounter;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+pub struct Noise {
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    mode: bool,
+    shift_register: u16,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+            mode: false,
+            shift_register: 1,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+        }
+    }
+
+    // $400C: --LC VVVV
+    pub fn write_control(&mut self, data: u8) {
+        self.length.halt = data & 0b0010_0000 != 0;
+        self.envelope.update(data);
+    }
+
+    // $400E: M--- PPPP
+    pub fn write_period(&mut self, data: u8) {
+        self.mode = data & 0b1000_0000 != 0;
+        self.timer_period = PERIOD_TABLE[(data & 0b1111) as usize];
+    }
+
+    // $400F: LLLL L---
+    pub fn write_length(&mut self, data: u8) {
+        self.length.load(data >> 3);
+        self.envelope.start = true;
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register & 1) ^ ((self.shift_register >> tap) & 1);
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.shift_register & 1 == 1 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Noise {
+    fn save(&self, state: &mut StateWriter) {
+        self.envelope.save(state);
+        self.length.save(state);
+        state.bool(self.mode);
+        state.u16(self.shift_register);
+        state.u16(self.timer_period);
+        state.u16(self.timer);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.envelope.load(state);
+        Snapshot::load(&mut self.length, state);
+        self.mode = state.bool();
+        self.shift_register = state.u16();
+        self.timer_period = state.u16();
+        self.timer = state.u16();
+    }
+}
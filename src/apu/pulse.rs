@@ -0,0 +1,192 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length::LengthCounter;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PulseChannel {
+    One,
+    Two,
+}
+
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Sweep {
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            reload: false,
+            divider: 0,
+        }
+    }
+}
+
+pub struct Pulse {
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    channel: PulseChannel,
+    sweep: Sweep,
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Pulse {
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+            channel,
+            sweep: Sweep::new(),
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+        }
+    }
+
+    // $4000/$4004: DDLC VVVV
+    pub fn write_control(&mut self, data: u8) {
+        self.duty = data >> 6;
+        self.length.halt = data & 0b0010_0000 != 0;
+        self.envelope.update(data);
+    }
+
+    // $4001/$4005: EPPP NSSS
+    pub fn write_sweep(&mut self, data: u8) {
+        self.sweep.enabled = data & 0b1000_0000 != 0;
+        self.sweep.period = (data >> 4) & 0b111;
+        self.sweep.negate = data & 0b0000_1000 != 0;
+        self.sweep.shift = data & 0b111;
+        self.sweep.reload = true;
+    }
+
+    // $4002/$4006
+    pub fn write_timer_lo(&mut self, data: u8) {
+        self.timer_period = (self.timer_period & 0xFF00) | data as u16;
+    }
+
+    // $4003/$4007: LLLL LHHH
+    pub fn write_timer_hi(&mut self, data: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | (((data & 0b111) as u16) << 8);
+        self.length.load(data >> 3);
+        self.sequence = 0;
+        self.envelope.start = true;
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let target = self.sweep_target();
+        if self.sweep.divider == 0
+            && self.sweep.enabled
+            && self.sweep.shift > 0
+            && !self.is_muted(target)
+        {
+            self.timer_period = target;
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.negate {
+            // pulse 1 negates with one's complement, pulse 2 with two's complement
+            match self.channel {
+                PulseChannel::One => self.timer_period.saturating_sub(change + 1),
+                PulseChannel::Two => self.timer_period.saturating_sub(change),
+            }
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    // the sweep unit mutes the channel even when it is disabled
+    fn is_muted(&self, target: u16) -> bool {
+        self.timer_period < 8 || target > 0x7FF
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active()
+            || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0
+            || self.is_muted(self.sweep_target())
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Sweep {
+    fn save(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.u8(self.period);
+        state.bool(self.negate);
+        state.u8(self.shift);
+        state.bool(self.reload);
+        state.u8(self.divider);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.enabled = state.bool();
+        self.period = state.u8();
+        self.negate = state.bool();
+        self.shift = state.u8();
+        self.reload = state.bool();
+        self.divider = state.u8();
+    }
+}
+
+impl Snapshot for Pulse {
+    fn save(&self, state: &mut StateWriter) {
+        self.envelope.save(state);
+        self.length.save(state);
+        self.sweep.save(state);
+        state.u8(self.duty);
+        state.u8(self.sequence);
+        state.u16(self.timer_period);
+        state.u16(self.timer);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.envelope.load(state);
+        Snapshot::load(&mut self.length, state);
+        self.sweep.load(state);
+        self.duty = state.u8();
+        self.sequence = state.u8();
+        self.timer_period = state.u16();
+        self.timer = state.u16();
+    }
+}
@@ -0,0 +1,109 @@
+use crate::apu::length::LengthCounter;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+pub struct Triangle {
+    pub length: LengthCounter,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Triangle {
+    pub fn new() -> Self {
+        Triangle {
+            length: LengthCounter::new(),
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+        }
+    }
+
+    // $4008: CRRR RRRR
+    pub fn write_linear_counter(&mut self, data: u8) {
+        self.control = data & 0b1000_0000 != 0;
+        self.length.halt = self.control;
+        self.linear_reload_value = data & 0b0111_1111;
+    }
+
+    // $400A
+    pub fn write_timer_lo(&mut self, data: u8) {
+        self.timer_period = (self.timer_period & 0xFF00) | data as u16;
+    }
+
+    // $400B: LLLL LHHH
+    pub fn write_timer_hi(&mut self, data: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | (((data & 0b111) as u16) << 8);
+        self.length.load(data >> 3);
+        self.linear_reload = true;
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length.is_active() && self.linear_counter > 0 {
+                self.sequence = (self.sequence + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        // ultrasonic periods are silenced instead of producing a popping DC level
+        if self.timer_period < 2 {
+            7
+        } else {
+            SEQUENCE[self.sequence as usize]
+        }
+    }
+}
+
+impl Snapshot for Triangle {
+    fn save(&self, state: &mut StateWriter) {
+        self.length.save(state);
+        state.bool(self.control);
+        state.u8(self.linear_reload_value);
+        state.u8(self.linear_counter);
+        state.bool(self.linear_reload);
+        state.u8(self.sequence);
+        state.u16(self.timer_period);
+        state.u16(self.timer);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        Snapshot::load(&mut self.length, state);
+        self.control = state.bool();
+        self.linear_reload_value = state.u8();
+        self.linear_counter = state.u8();
+        self.linear_reload = state.bool();
+        self.sequence = state.u8();
+        self.timer_period = state.u16();
+        self.timer = state.u16();
+    }
+}
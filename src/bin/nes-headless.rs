@@ -0,0 +1,241 @@
+//! Runs a ROM without a window or audio device, for build servers and batch jobs.
+//!
+//! ```text
+//! nes-headless <rom> [--frames N] [--until ADDR=VALUE] [--frame-out FILE.ppm] [--ram-out FILE]
+//!                    [--trace FILE] [--movie FILE.fm2] [--frame-hash] [--debug-view] [--debug]
+//! ```
+//!
+//! The emulator runs for `--frames` frames (60 by default). `--movie` replays an FM2
+//! movie until its input runs out, or for `--frames` frames if that is given, and
+//! `--frame-hash` prints a hash of the last frame for comparing runs. `--debug-view`
+//! saves and hashes the debug composite, the picture next to the nametables, instead
+//! of the 256x240 picture. With `--until` it stops as
+//! soon as the byte at ADDR equals VALUE (both hex, checked once per frame) and exits
+//! with status 1 if that never happens within the frame budget. `--trace` writes a
+//! nestest-style log line for every executed instruction. `--debug` opens the
+//! debugger prompt on stdin instead of running freely; type `help` for its commands.
+
+use nes::bus::Bus;
+use nes::cpu::trace::Tracer;
+use nes::cpu::CPU;
+use nes::debugger::{repl, Debugger};
+use nes::movie::{Movie, Player};
+use nes::render;
+use nes::render::frame::Frame;
+use nes::rom::Rom;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::process;
+
+const DEFAULT_FRAMES: usize = 60;
+
+struct Options {
+    rom_path: String,
+    frames: Option<usize>,
+    until: Option<(u16, u8)>,
+    frame_out: Option<String>,
+    ram_out: Option<String>,
+    trace: Option<String>,
+    movie: Option<String>,
+    frame_hash: bool,
+    debug_view: bool,
+    debug: bool,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: nes-headless <rom> [--frames N] [--until ADDR=VALUE] \
+         [--frame-out FILE.ppm] [--ram-out FILE] [--trace FILE] [--movie FILE.fm2] \
+         [--frame-hash] [--debug-view] [--debug]"
+    );
+    process::exit(2);
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    let digits = value.trim_start_matches('$').trim_start_matches("0x");
+    u32::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number '{}'", value))
+}
+
+fn parse_until(value: &str) -> Result<(u16, u8), String> {
+    let (addr, data) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDR=VALUE, got '{}'", value))?;
+    let addr =
+        u16::try_from(parse_hex(addr)?).map_err(|_| format!("address '{}' out of range", addr))?;
+    let data =
+        u8::try_from(parse_hex(data)?).map_err(|_| format!("value '{}' out of range", data))?;
+    Ok((addr, data))
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut frames = None;
+    let mut until = None;
+    let mut frame_out = None;
+    let mut ram_out = None;
+    let mut trace = None;
+    let mut movie = None;
+    let mut frame_hash = false;
+    let mut debug_view = false;
+    let mut debug = false;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--frames" => {
+                let value = value()?;
+                frames = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid frame count '{}'", value))?,
+                );
+            }
+            "--until" => until = Some(parse_until(&value()?)?),
+            "--frame-out" => frame_out = Some(value()?),
+            "--ram-out" => ram_out = Some(value()?),
+            "--trace" => trace = Some(value()?),
+            "--movie" => movie = Some(value()?),
+            "--frame-hash" => frame_hash = true,
+            "--debug-view" => debug_view = true,
+            "--debug" => debug = true,
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or("missing ROM path")?,
+        frames,
+        until,
+        frame_out,
+        ram_out,
+        trace,
+        movie,
+        frame_hash,
+        debug_view,
+        debug,
+    })
+}
+
+/// Returns whether the `--until` condition was met.
+fn run(options: &Options) -> Result<bool, String> {
+    let file = File::open(&options.rom_path)
+        .map_err(|err| format!("cannot open {}: {}", options.rom_path, err))?;
+    let mapper = Rom::from_reader(file)
+        .and_then(nes::bus::mapper::from_rom)
+        .map_err(|err| format!("{}: {}", options.rom_path, err))?;
+
+    let bus = Bus::with_mapper(mapper, |_, _| {});
+    let mut cpu = CPU::new(bus);
+    if let Some(path) = &options.trace {
+        let file = File::create(path).map_err(|err| format!("cannot create {}: {}", path, err))?;
+        cpu.set_tracer(Tracer::new(BufWriter::new(file)));
+    }
+    cpu.reset();
+
+    let movie = match &options.movie {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {}: {}", path, err))?;
+            Some(Movie::from_fm2(&text).map_err(|err| format!("{}: {}", path, err))?)
+        }
+        None => None,
+    };
+    let mut player = match &movie {
+        Some(movie) => Some(Player::new(movie, &mut cpu)?),
+        None => None,
+    };
+    let frames = match (options.frames, &player) {
+        (Some(frames), _) => frames,
+        (None, Some(_)) => usize::MAX,
+        (None, None) => DEFAULT_FRAMES,
+    };
+
+    let mut condition_met = false;
+    let mut last_frame = cpu.bus.ppu.frame_count();
+    if options.debug {
+        repl::run(
+            &mut Debugger::new(),
+            &mut cpu,
+            io::stdin().lock(),
+            io::stdout(),
+        )
+        .map_err(|err| format!("debugger: {}", err))?;
+    }
+    while !options.debug && cpu.bus.ppu.frame_count() < frames {
+        if let Some(player) = &mut player {
+            if !player.update(&mut cpu) {
+                break;
+            }
+        }
+        cpu.step();
+
+        if cpu.bus.ppu.frame_count() != last_frame {
+            last_frame = cpu.bus.ppu.frame_count();
+            if let Some((addr, data)) = options.until {
+                if cpu.bus.peek(addr) == data {
+                    condition_met = true;
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(tracer) = cpu.tracer_mut() {
+        tracer.flush();
+    }
+    eprintln!(
+        "ran {} frames ({} CPU cycles)",
+        cpu.bus.ppu.frame_count(),
+        cpu.bus.cycles()
+    );
+
+    let frame = if options.debug_view {
+        let mut frame = Frame::with_size(render::DEBUG_WIDTH, render::DEBUG_HEIGHT);
+        render::render_debug(&cpu.bus.ppu, &mut frame);
+        frame
+    } else {
+        let mut frame = Frame::new();
+        render::render(&cpu.bus.ppu, &mut frame);
+        frame
+    };
+    if options.frame_hash {
+        println!("{:016x}", frame.hash());
+    }
+
+    if let Some(path) = &options.frame_out {
+        let file = File::create(path).map_err(|err| format!("cannot create {}: {}", path, err))?;
+        frame
+            .write_ppm(&mut BufWriter::new(file))
+            .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    }
+
+    if let Some(path) = &options.ram_out {
+        std::fs::write(path, cpu.bus.ram)
+            .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    }
+
+    Ok(condition_met || options.until.is_none())
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        usage();
+    });
+
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("condition not met");
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        }
+    }
+}
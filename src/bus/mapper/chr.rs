@@ -0,0 +1,67 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+/// CHR RAM on iNES carts, which do not say how much they have.
+pub const DEFAULT_CHR_RAM_SIZE: usize = 0x2000;
+
+/// The cartridge's pattern table memory: its CHR ROM, or CHR RAM on boards that
+/// have no CHR ROM and let the CPU fill the pattern tables through $2007.
+pub struct Chr {
+    data: Vec<u8>,
+    is_ram: bool,
+}
+
+impl Chr {
+    /// `chr_rom`, or `ram_size` bytes of CHR RAM if it is empty.
+    pub fn new(chr_rom: Vec<u8>, ram_size: usize) -> Self {
+        if chr_rom.is_empty() {
+            Chr {
+                data: vec![0; ram_size.max(1)],
+                is_ram: true,
+            }
+        } else {
+            Chr {
+                data: chr_rom,
+                is_ram: false,
+            }
+        }
+    }
+
+    pub fn is_ram(&self) -> bool {
+        self.is_ram
+    }
+
+    /// Reads the byte at `addr`, wrapping around the end of the memory like the
+    /// unconnected upper bank lines on a small chip.
+    pub fn read(&self, addr: usize) -> u8 {
+        self.data[addr % self.data.len()]
+    }
+
+    /// Writes are ignored by CHR ROM.
+    pub fn write(&mut self, addr: usize, data: u8) {
+        if self.is_ram {
+            let len = self.data.len();
+            self.data[addr % len] = data;
+        }
+    }
+}
+
+impl From<Vec<u8>> for Chr {
+    fn from(chr_rom: Vec<u8>) -> Self {
+        Chr::new(chr_rom, DEFAULT_CHR_RAM_SIZE)
+    }
+}
+
+/// Only CHR RAM is saved, CHR ROM comes from the cartridge.
+impl Snapshot for Chr {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(if self.is_ram { &self.data } else { &[] });
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        if self.is_ram {
+            state.bytes_into(&mut self.data);
+        } else {
+            state.bytes_into(&mut []);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::bus::mapper::{Chr, Mapper};
+use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+
+/// NROM: fixed 16K/32K PRG ROM, 8K CHR ROM or RAM and the optional 8K PRG RAM at $6000.
+pub struct Mapper0 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    mirroring: Mirroring,
+}
+
+impl Mapper0 {
+    pub fn new(prg_rom: Vec<u8>, chr: impl Into<Chr>, mirroring: Mirroring) -> Self {
+        Self {
+            prg_rom,
+            chr: chr.into(),
+            prg_ram: [0; PRG_RAM_SIZE],
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper0 {
+    fn read_prg_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                // 16K carts mirror $8000-$BFFF into $C000-$FFFF
+                let prg_rom_addr = (addr - 0x8000) as usize % self.prg_rom.len();
+                self.prg_rom[prg_rom_addr]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg_byte(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn read_chr_byte(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr_byte(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Mapper0 {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.prg_ram);
+        self.chr.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.prg_ram);
+        self.chr.load(state);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::bus::mapper::{Chr, Mapper};
+use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const SHIFT_REGISTER_RESET: u8 = 0b1_0000;
+
+/// MMC1 (SxROM): registers are loaded one bit at a time through a 5-bit
+/// shift register mapped over $8000-$FFFF.
+pub struct Mapper1 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    battery: bool,
+
+    shift_register: u8,
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    prg_bank: u8,
+}
+
+impl Mapper1 {
+    pub fn new(prg_rom: Vec<u8>, chr: impl Into<Chr>, battery: bool) -> Self {
+        Mapper1 {
+            prg_rom,
+            chr: chr.into(),
+            prg_ram: [0; PRG_RAM_SIZE],
+            battery,
+
+            shift_register: SHIFT_REGISTER_RESET,
+            // power-on state fixes the last PRG bank at $C000
+            control: 0b0_1100,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.chr_bank0 = data,
+            0xC000..=0xDFFF => self.chr_bank1 = data,
+            _ => self.prg_bank = data,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0b1_0000 == 0
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+
+    // 512K boards (SUROM) use bit 4 of the CHR bank register to select the PRG half
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_rom.len() > 0x40000 {
+            (self.chr_bank0 & 0b1_0000) as usize
+        } else {
+            0
+        }
+    }
+
+    fn prg_bank_for(&self, addr: u16) -> usize {
+        let bank = (self.prg_bank & 0b1111) as usize;
+        let outer = self.prg_outer_bank();
+        let last = (outer | 0b1111).min(self.prg_bank_count() - 1);
+        let low_half = addr < 0xC000;
+
+        match (self.control >> 2) & 0b11 {
+            // 32K mode ignores the low bit of the bank number
+            0 | 1 => outer | (bank & !1) | if low_half { 0 } else { 1 },
+            2 if low_half => outer,
+            2 => outer | bank,
+            _ if low_half => outer | bank,
+            _ => last,
+        }
+    }
+
+    fn chr_addr(&self, addr: u16) -> usize {
+        let (bank, offset) = if self.control & 0b1_0000 == 0 {
+            // 8K mode ignores the low bit of the bank number
+            let bank = (self.chr_bank0 & !1) as usize + (addr as usize / CHR_BANK_SIZE);
+            (bank, addr as usize % CHR_BANK_SIZE)
+        } else if addr < 0x1000 {
+            (self.chr_bank0 as usize, addr as usize)
+        } else {
+            (self.chr_bank1 as usize, addr as usize - CHR_BANK_SIZE)
+        };
+        bank * CHR_BANK_SIZE + offset
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn read_prg_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank_for(addr) % self.prg_bank_count();
+                let offset = (addr as usize - 0x8000) % PRG_BANK_SIZE;
+                self.prg_rom
+                    .get(bank * PRG_BANK_SIZE + offset)
+                    .copied()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg_byte(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0xFFFF => {
+                if data & 0b1000_0000 != 0 {
+                    self.shift_register = SHIFT_REGISTER_RESET;
+                    self.control |= 0b0_1100;
+                    return;
+                }
+
+                // the register is full once the initial marker bit reaches bit 0
+                let complete = self.shift_register & 1 == 1;
+                self.shift_register = (self.shift_register >> 1) | ((data & 1) << 4);
+                if complete {
+                    self.write_register(addr, self.shift_register);
+                    self.shift_register = SHIFT_REGISTER_RESET;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr_byte(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_addr(addr))
+    }
+
+    fn write_chr_byte(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_addr(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        if self.battery {
+            Some(&self.prg_ram)
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(PRG_RAM_SIZE);
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl Snapshot for Mapper1 {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.prg_ram);
+        self.chr.save(state);
+        state.u8(self.shift_register);
+        state.u8(self.control);
+        state.u8(self.chr_bank0);
+        state.u8(self.chr_bank1);
+        state.u8(self.prg_bank);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.prg_ram);
+        self.chr.load(state);
+        self.shift_register = state.u8();
+        self.control = state.u8();
+        self.chr_bank0 = state.u8();
+        self.chr_bank1 = state.u8();
+        self.prg_bank = state.u8();
+    }
+}
@@ -0,0 +1,74 @@
+use crate::bus::mapper::{Chr, Mapper};
+use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// UxROM: a 16K PRG bank at $8000 selected by any write to $8000-$FFFF, the last
+/// bank fixed at $C000, and usually 8K of CHR RAM.
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    prg_bank: u8,
+}
+
+impl Mapper2 {
+    pub fn new(prg_rom: Vec<u8>, chr: impl Into<Chr>, mirroring: Mirroring) -> Self {
+        Mapper2 {
+            prg_rom,
+            chr: chr.into(),
+            mirroring,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn read_prg_byte(&self, addr: u16) -> u8 {
+        if addr < 0x8000 || self.prg_rom.is_empty() {
+            return 0;
+        }
+        let bank = if addr < 0xC000 {
+            self.prg_bank as usize % self.prg_bank_count()
+        } else {
+            self.prg_bank_count() - 1
+        };
+        let prg_addr = bank * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE);
+        self.prg_rom[prg_addr % self.prg_rom.len()]
+    }
+
+    fn write_prg_byte(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            self.prg_bank = data;
+        }
+    }
+
+    fn read_chr_byte(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr_byte(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Mapper2 {
+    fn save(&self, state: &mut StateWriter) {
+        self.chr.save(state);
+        state.u8(self.prg_bank);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.chr.load(state);
+        self.prg_bank = state.u8();
+    }
+}
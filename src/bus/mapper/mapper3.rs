@@ -0,0 +1,76 @@
+use crate::bus::mapper::{Chr, Mapper};
+use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+/// CNROM: fixed 16K/32K PRG ROM with 8K CHR banks selected by any write to $8000-$FFFF.
+pub struct Mapper3 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    mirroring: Mirroring,
+    chr_bank: u8,
+}
+
+impl Mapper3 {
+    pub fn new(prg_rom: Vec<u8>, chr: impl Into<Chr>, mirroring: Mirroring) -> Self {
+        Self {
+            prg_rom,
+            chr: chr.into(),
+            prg_ram: [0; PRG_RAM_SIZE],
+            mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn read_prg_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                let prg_rom_addr = (addr - 0x8000) as usize % self.prg_rom.len();
+                self.prg_rom[prg_rom_addr]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg_byte(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0x8000..=0xFFFF => self.chr_bank = data,
+            _ => {}
+        }
+    }
+
+    fn read_chr_byte(&self, addr: u16) -> u8 {
+        self.chr
+            .read(self.chr_bank as usize * CHR_BANK_SIZE + addr as usize)
+    }
+
+    fn write_chr_byte(&mut self, addr: u16, data: u8) {
+        self.chr
+            .write(self.chr_bank as usize * CHR_BANK_SIZE + addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Mapper3 {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.prg_ram);
+        state.u8(self.chr_bank);
+        self.chr.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.prg_ram);
+        self.chr_bank = state.u8();
+        self.chr.load(state);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::bus::mapper::{Chr, Mapper};
+use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+/// MMC3 (TxROM): eight bank registers selected through $8000/$8001 and a
+/// scanline counter clocked by rising edges on PPU A12.
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    battery: bool,
+    four_screen: bool,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mapper4 {
+    pub fn new(prg_rom: Vec<u8>, chr: impl Into<Chr>, mirroring: Mirroring, battery: bool) -> Self {
+        Mapper4 {
+            prg_rom,
+            chr: chr.into(),
+            prg_ram: [0; PRG_RAM_SIZE],
+            battery,
+            four_screen: mirroring == Mirroring::FourScreen,
+
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+
+    fn prg_bank_for(&self, addr: u16) -> usize {
+        let second_last = self.prg_bank_count().saturating_sub(2);
+        let prg_mode = self.bank_select & 0b0100_0000 != 0;
+
+        match (addr, prg_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.registers[6] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize,
+            _ => self.prg_bank_count() - 1,
+        }
+    }
+
+    fn chr_addr(&self, addr: u16) -> usize {
+        // CHR A12 inversion swaps the 2K and 1K halves of the pattern tables
+        let addr = if self.bank_select & 0b1000_0000 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        } as usize;
+
+        let bank = match addr / CHR_BANK_SIZE {
+            0 => self.registers[0] & 0xFE,
+            1 => self.registers[0] | 0x01,
+            2 => self.registers[1] & 0xFE,
+            3 => self.registers[1] | 0x01,
+            slot => self.registers[slot - 2],
+        } as usize;
+
+        bank * CHR_BANK_SIZE + addr % CHR_BANK_SIZE
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn signal_scanline(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn read_prg_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank_for(addr) % self.prg_bank_count();
+                let offset = addr as usize % PRG_BANK_SIZE;
+                self.prg_rom
+                    .get(bank * PRG_BANK_SIZE + offset)
+                    .copied()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg_byte(&mut self, addr: u16, data: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = data,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0b111) as usize] = data,
+            // four-screen boards have hardwired mirroring
+            0xA000..=0xBFFF if even && self.four_screen => {}
+            0xA000..=0xBFFF if even => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_write_protect = data & 0b0100_0000 != 0;
+            }
+            0xC000..=0xDFFF if even => self.irq_latch = data,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn read_chr_byte(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_addr(addr))
+    }
+
+    fn write_chr_byte(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_addr(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        if self.battery {
+            Some(&self.prg_ram)
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(PRG_RAM_SIZE);
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl Snapshot for Mapper4 {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.prg_ram);
+        self.chr.save(state);
+        state.u8(self.bank_select);
+        state.bytes(&self.registers);
+        self.mirroring.save(state);
+        state.bool(self.prg_ram_enabled);
+        state.bool(self.prg_ram_write_protect);
+        state.u8(self.irq_latch);
+        state.u8(self.irq_counter);
+        state.bool(self.irq_reload);
+        state.bool(self.irq_enabled);
+        state.bool(self.irq_pending);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.prg_ram);
+        self.chr.load(state);
+        self.bank_select = state.u8();
+        state.bytes_into(&mut self.registers);
+        self.mirroring.load(state);
+        self.prg_ram_enabled = state.bool();
+        self.prg_ram_write_protect = state.bool();
+        self.irq_latch = state.u8();
+        self.irq_counter = state.u8();
+        self.irq_reload = state.bool();
+        self.irq_enabled = state.bool();
+        self.irq_pending = state.bool();
+    }
+}
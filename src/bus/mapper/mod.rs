@@ -0,0 +1,96 @@
+mod chr;
+mod mapper0;
+mod mapper1;
+mod mapper2;
+mod mapper3;
+mod mapper4;
+
+pub use chr::{Chr, DEFAULT_CHR_RAM_SIZE};
+pub use mapper0::Mapper0;
+pub use mapper1::Mapper1;
+pub use mapper2::Mapper2;
+pub use mapper3::Mapper3;
+pub use mapper4::Mapper4;
+
+use crate::rom::{Mirroring, Rom, RomError};
+use crate::savestate::Snapshot;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Cartridge hardware, shared between the CPU bus ($4020-$FFFF) and the PPU
+/// pattern tables ($0000-$1FFF).
+pub type SharedMapper = Rc<RefCell<dyn Mapper>>;
+
+/// Save states must capture every register and any RAM on the cartridge.
+pub trait Mapper: Snapshot {
+    /// Clocked once per rendered scanline, when PPU A12 rises for the sprite fetches.
+    fn signal_scanline(&mut self) {}
+
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    fn read_prg_byte(&self, addr: u16) -> u8;
+    fn write_prg_byte(&mut self, addr: u16, data: u8);
+    fn read_chr_byte(&self, addr: u16) -> u8;
+    fn write_chr_byte(&mut self, addr: u16, data: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    /// What a CPU read of `addr` would return, for debuggers and memory viewers.
+    /// Mappers whose reads have side effects (latches, IRQ acknowledges) must
+    /// override this to leave their state alone.
+    fn peek_prg_byte(&self, addr: u16) -> u8 {
+        self.read_prg_byte(addr)
+    }
+
+    /// Like `peek_prg_byte`, for the PPU pattern tables.
+    fn peek_chr_byte(&self, addr: u16) -> u8 {
+        self.read_chr_byte(addr)
+    }
+
+    /// Battery-backed PRG RAM that should be persisted between sessions.
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn load_battery_ram(&mut self, _data: &[u8]) {}
+}
+
+pub fn from_rom(rom: Rom) -> Result<SharedMapper, RomError> {
+    // boards without CHR ROM get the CHR RAM the header asks for
+    let chr_ram_size = match rom.chr_ram_size + rom.chr_nvram_size {
+        0 => DEFAULT_CHR_RAM_SIZE,
+        size => size,
+    };
+    let chr = Chr::new(rom.chr_rom, chr_ram_size);
+
+    match rom.mapper {
+        0 => Ok(Rc::new(RefCell::new(Mapper0::new(
+            rom.prg_rom,
+            chr,
+            rom.mirroring,
+        )))),
+        1 => Ok(Rc::new(RefCell::new(Mapper1::new(
+            rom.prg_rom,
+            chr,
+            rom.battery,
+        )))),
+        2 => Ok(Rc::new(RefCell::new(Mapper2::new(
+            rom.prg_rom,
+            chr,
+            rom.mirroring,
+        )))),
+        3 => Ok(Rc::new(RefCell::new(Mapper3::new(
+            rom.prg_rom,
+            chr,
+            rom.mirroring,
+        )))),
+        4 => Ok(Rc::new(RefCell::new(Mapper4::new(
+            rom.prg_rom,
+            chr,
+            rom.mirroring,
+            rom.battery,
+        )))),
+        id => Err(RomError::UnsupportedMapper(id)),
+    }
+}
@@ -1,45 +1,75 @@
+pub mod mapper;
+
+use crate::apu::APU;
+use crate::joypad::{InputDevice, Joypad};
 use crate::ppu::PPU;
-use crate::rom::Rom;
+use crate::rom::{Rom, RomError};
+use crate::savestate::{Chunks, Snapshot, StateReader, StateWriter};
+use bitflags::bitflags;
+use mapper::SharedMapper;
 
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const CARTRIDGE_SPACE: u16 = 0x4020;
+const DMC_FETCH_STALL_CYCLES: u8 = 4;
+// controller reads only drive the low 5 bits, the rest float at the last bus value
+const JOYPAD_OPEN_BUS_MASK: u8 = 0b1110_0000;
+
+bitflags! {
+    /// Devices currently pulling the shared /IRQ line low.
+    pub struct IrqSource: u8 {
+        const APU_FRAME = 0b00000001;
+        const DMC       = 0b00000010;
+        const MAPPER    = 0b00000100;
+    }
+}
+
+type GameloopCallback<'call> = Box<dyn FnMut(&PPU, &mut [Box<dyn InputDevice>; 2]) + 'call>;
 
 pub struct Bus<'call> {
     pub ram: [u8; 0x800],
     pub ppu: PPU,
-    prg_rom: Vec<u8>,
+    pub apu: APU,
+    pub joypads: [Box<dyn InputDevice>; 2],
+    pub mapper: SharedMapper,
     cycles: usize,
-    gameloop_callback: Box<dyn FnMut(&PPU) + 'call>,
+    open_bus: u8,
+    gameloop_callback: GameloopCallback<'call>,
 }
 
 impl<'a> Bus<'a> {
-    pub fn new<'call, F>(rom: Rom, gameloop_callback: F) -> Bus<'call>
+    /// Fails with `RomError::UnsupportedMapper` if the cartridge uses a mapper that
+    /// is not implemented.
+    pub fn new<'call, F>(rom: Rom, gameloop_callback: F) -> Result<Bus<'call>, RomError>
+    where
+        F: FnMut(&PPU, &mut [Box<dyn InputDevice>; 2]) + 'call,
+    {
+        let mapper = mapper::from_rom(rom)?;
+        Ok(Bus::with_mapper(mapper, gameloop_callback))
+    }
+
+    pub fn with_mapper<'call, F>(mapper: SharedMapper, gameloop_callback: F) -> Bus<'call>
     where
-        F: FnMut(&PPU) + 'call,
+        F: FnMut(&PPU, &mut [Box<dyn InputDevice>; 2]) + 'call,
     {
-        let ppu = PPU::new(rom.chr_rom, rom.mirroring);
+        let ppu = PPU::with_mapper(mapper.clone());
 
         Bus {
             ram: [0; 0x800],
             ppu: ppu,
-            prg_rom: rom.prg_rom,
+            apu: APU::new(),
+            joypads: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            mapper,
             cycles: 0,
+            open_bus: 0,
             gameloop_callback: Box::from(gameloop_callback),
         }
     }
 
-    fn read_prg_rom(&self, addr: u16) -> u8 {
-        let mut prg_rom_addr = addr - 0x8000;
-        if self.prg_rom.len() == 0x4000 && prg_rom_addr >= 0x4000 {
-            prg_rom_addr = prg_rom_addr % 0x4000;
-        }
-        self.prg_rom[prg_rom_addr as usize]
-    }
-
     pub fn mem_read(&mut self, addr: u16) -> u8 {
-        match addr {
+        let data = match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00000111_11111111;
                 self.ram[mirror_down_addr as usize]
@@ -49,34 +79,32 @@ impl<'a> Bus<'a> {
             0x2004 => self.ppu.registers.read_oam_data(),
             0x2007 => self.ppu.read_data(),
 
-            0x4000..=0x4015 => {
-                //ignore APU
-                0
-            }
+            0x4015 => self.apu.read_status(),
 
-            0x4016 => {
-                // self.joypad1.read()
+            0x4000..=0x4013 => {
+                // APU registers are write-only
                 0
             }
 
-            0x4017 => {
-                // ignore joypad 2
-                0
-            }
+            0x4016 => (self.open_bus & JOYPAD_OPEN_BUS_MASK) | self.joypads[0].read(),
+            0x4017 => (self.open_bus & JOYPAD_OPEN_BUS_MASK) | self.joypads[1].read(),
             0x2008..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
                 self.mem_read(mirror_down_addr)
             }
-            0x8000..=0xFFFF => self.read_prg_rom(addr),
+            CARTRIDGE_SPACE..=0xFFFF => self.mapper.borrow().read_prg_byte(addr),
 
             _ => {
                 // println!("Ignoring mem access at {:x}", addr);
                 0
             }
-        }
+        };
+        self.open_bus = data;
+        data
     }
 
     pub fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b11111111111;
@@ -89,7 +117,8 @@ impl<'a> Bus<'a> {
                 self.ppu.registers.write_to_mask(data);
             }
 
-            0x2002 => panic!("attempt to write to PPU status register"),
+            // PPUSTATUS is read-only, writes only refresh the open bus
+            0x2002 => {}
 
             0x2003 => {
                 self.ppu.registers.write_to_oam_addr(data);
@@ -107,23 +136,31 @@ impl<'a> Bus<'a> {
             0x2007 => {
                 self.ppu.write_to_data(data);
             }
-            0x4000..=0x4013 | 0x4015 => {
-                //ignore APU
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.apu.write_register(addr, data);
             }
 
             0x4016 => {
-                // ignore joypad 1
-            }
-
-            0x4017 => {
-                // ignore joypad 2
+                // the strobe line is shared by both ports
+                for joypad in self.joypads.iter_mut() {
+                    joypad.write(data);
+                }
             }
 
             0x4014 => {
+                // the CPU halts for one cycle, one more to align to a read cycle,
+                // then the DMA unit alternates reading a byte and writing it to OAM
+                self.tick(1);
+                if self.cycles % 2 == 1 {
+                    self.tick(1);
+                }
+
                 let mut buffer: [u8; 0x100] = [0; 0x100];
                 let hi: u16 = (data as u16) << 8;
                 for i in 0..256u16 {
-                    buffer[i as usize] = self.mem_read(hi + i)
+                    self.tick(1);
+                    buffer[i as usize] = self.mem_read(hi + i);
+                    self.tick(1);
                 }
                 self.ppu.write_oam_dma(&buffer);
             }
@@ -132,14 +169,66 @@ impl<'a> Bus<'a> {
                 let mirror_down_addr = addr & 0b00100000_00000111;
                 self.mem_write(mirror_down_addr, data);
             }
-            0x8000..=0xFFFF => panic!("Attempt to write to Cartridge ROM space: {:x}", addr),
+            CARTRIDGE_SPACE..=0xFFFF => self.mapper.borrow_mut().write_prg_byte(addr, data),
 
             _ => {
-                println!("Ignoring mem write-access at {:x}", addr);
+                log::trace!("Ignoring mem write-access at {:x}", addr);
             }
         }
     }
 
+    /// Returns what `mem_read(addr)` would return without mutating anything: PPU
+    /// latches, the $2007 buffer, controller shift registers, the APU frame IRQ and
+    /// mapper state are left untouched. Meant for debuggers and memory viewers.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.ram[(addr & 0b00000111_11111111) as usize],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.peek_register(addr),
+            0x4015 => self.apu.peek_status(),
+            0x4016 => (self.open_bus & JOYPAD_OPEN_BUS_MASK) | self.joypads[0].peek(),
+            0x4017 => (self.open_bus & JOYPAD_OPEN_BUS_MASK) | self.joypads[1].peek(),
+            CARTRIDGE_SPACE..=0xFFFF => self.mapper.borrow().peek_prg_byte(addr),
+            _ => 0,
+        }
+    }
+
+    pub fn irq_sources(&self) -> IrqSource {
+        let mut sources = IrqSource::empty();
+        sources.set(IrqSource::APU_FRAME, self.apu.frame_irq_pending());
+        sources.set(IrqSource::DMC, self.apu.dmc_irq_pending());
+        sources.set(IrqSource::MAPPER, self.mapper.borrow().irq_pending());
+        sources
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        !self.irq_sources().is_empty()
+    }
+
+    /// CPU cycles elapsed since power-on, including DMA stalls.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Writes RAM, the PPU, the APU, both controller ports and the cartridge as
+    /// separate chunks.
+    pub fn save_chunks(&self, state: &mut StateWriter) {
+        state.chunk(*b"BUS ", |state| self.save(state));
+        state.chunk(*b"PPU ", |state| self.ppu.save(state));
+        state.chunk(*b"APU ", |state| self.apu.save(state));
+        state.chunk(*b"JOY1", |state| self.joypads[0].save(state));
+        state.chunk(*b"JOY2", |state| self.joypads[1].save(state));
+        state.chunk(*b"CART", |state| self.mapper.borrow().save(state));
+    }
+
+    pub fn load_chunks(&mut self, chunks: &Chunks) {
+        chunks.load(*b"BUS ", self);
+        chunks.load(*b"PPU ", &mut self.ppu);
+        chunks.load(*b"APU ", &mut self.apu);
+        chunks.load(*b"JOY1", self.joypads[0].as_mut());
+        chunks.load(*b"JOY2", self.joypads[1].as_mut());
+        chunks.load(*b"CART", &mut *self.mapper.borrow_mut());
+    }
+
     pub fn read_word(&mut self, addr: u16) -> u16 {
         let lo = self.mem_read(addr) as u16;
         let hi = self.mem_read(addr + 1) as u16;
@@ -149,12 +238,42 @@ impl<'a> Bus<'a> {
     pub fn tick(&mut self, cycles: u8) {
         self.cycles += cycles as usize;
 
+        let mut stall = 0;
+        for _ in 0..cycles {
+            self.apu.tick();
+            if let Some(addr) = self.apu.dmc_fetch_request() {
+                let data = self.mem_read(addr);
+                self.apu.fill_dmc_sample(data);
+                stall += DMC_FETCH_STALL_CYCLES;
+            }
+        }
+
         let nmi_before = self.ppu.registers.nmi_interrupt.is_some();
         self.ppu.tick(cycles * 3);
         let nmi_after = self.ppu.registers.nmi_interrupt.is_some();
 
         if !nmi_before && nmi_after {
-            (self.gameloop_callback)(&self.ppu);
+            (self.gameloop_callback)(&self.ppu, &mut self.joypads);
+        }
+
+        // the CPU is halted while the DMC memory reader fetches a sample
+        if stall > 0 {
+            self.tick(stall);
         }
     }
 }
+
+/// The bus' own chunk, the devices behind it are saved by `save_chunks`.
+impl Snapshot for Bus<'_> {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.usize(self.cycles);
+        state.u8(self.open_bus);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.ram);
+        self.cycles = state.usize();
+        self.open_bus = state.u8();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::cpu::{StatusFlags, CPU};
+use crate::cpu::{interrupt, StatusFlags, CPU, STACK};
 
 #[derive(Debug)]
 pub enum AddressMode {
@@ -18,14 +18,9 @@ pub enum AddressMode {
 macro_rules! logical {
     ($fn_name:ident, $op:tt) => {
         pub fn $fn_name(&mut self, mode: AddressMode) {
-            let (addr, page_cross) = self.get_operand(mode);
-            let value = self.bus.mem_read(addr);
-            self.a = self.a $op value;
+            let value = self.read_operand(mode);
+            self.a $op value;
             self.update_zero_and_negative_flags(self.a);
-
-            if page_cross {
-                self.bus.tick(1);
-            }
         }
     };
 }
@@ -33,14 +28,9 @@ macro_rules! logical {
 macro_rules! load_register {
     ($fn_name:ident, $reg:ident) => {
         pub fn $fn_name(&mut self, mode: AddressMode) {
-            let (addr, page_cross) = self.get_operand(mode);
-            let value = self.bus.mem_read(addr);
+            let value = self.read_operand(mode);
             self.$reg = value;
             self.update_zero_and_negative_flags(self.$reg);
-
-            if page_cross {
-                self.bus.tick(1);
-            }
         }
     };
 }
@@ -48,16 +38,9 @@ macro_rules! load_register {
 macro_rules! branch {
     ($fn_name:ident, $flag:ident, $condition:expr) => {
         pub fn $fn_name(&mut self) {
+            let jump = self.fetch_byte() as i8;
             if $condition(self.status.contains(StatusFlags::$flag)) {
-                self.bus.tick(1);
-                let jump = self.bus.mem_read(self.pc) as i8;
-                let jump_addr = self.pc.wrapping_add(1).wrapping_add(jump as u16);
-
-                if self.pc.wrapping_add(1) & 0xFF00 != jump_addr & 0xFF00 {
-                    self.bus.tick(1);
-                }
-
-                self.pc = jump_addr;
+                self.branch(jump);
             }
         }
     };
@@ -66,16 +49,8 @@ macro_rules! branch {
 macro_rules! compare {
     ($fn_name:ident, $reg:ident) => {
         pub fn $fn_name(&mut self, mode: AddressMode) {
-            let (addr, page_cross) = self.get_operand(mode);
-            let value = self.bus.mem_read(addr);
-            let result = self.$reg.wrapping_sub(value);
-            self.status.set(StatusFlags::CARRY, self.$reg >= value);
-            self.status.set(StatusFlags::ZERO, self.$reg == value);
-            self.status.set(StatusFlags::NEGATIVE, result & 0x80 != 0);
-
-            if page_cross {
-                self.bus.tick(1);
-            }
+            let value = self.read_operand(mode);
+            self.compare(self.$reg, value);
         }
     };
 }
@@ -83,8 +58,8 @@ macro_rules! compare {
 macro_rules! store {
     ($fn_name:ident, $reg:ident) => {
         pub fn $fn_name(&mut self, mode: AddressMode) {
-            let (addr, _) = self.get_operand(mode);
-            self.bus.mem_write(addr, self.$reg);
+            let addr = self.get_operand_address(mode, true);
+            self.write_byte(addr, self.$reg);
         }
     };
 }
@@ -101,10 +76,7 @@ macro_rules! transfer {
 macro_rules! increment {
     (inc, $reg:ident) => {
         pub fn inc(&mut self, mode: AddressMode) {
-            let (addr, _) = self.get_operand(mode);
-            let mut value = self.bus.mem_read(addr);
-            value = value.wrapping_add(1);
-            self.bus.mem_write(addr, value);
+            let value = self.read_modify_write(mode, |_, value| value.wrapping_add(1));
             self.update_zero_and_negative_flags(value);
         }
     };
@@ -119,10 +91,7 @@ macro_rules! increment {
 macro_rules! decrement {
     (dec, $reg:ident) => {
         pub fn dec(&mut self, mode: AddressMode) {
-            let (addr, _) = self.get_operand(mode);
-            let mut value = self.bus.mem_read(addr);
-            value = value.wrapping_sub(1);
-            self.bus.mem_write(addr, value);
+            let value = self.read_modify_write(mode, |_, value| value.wrapping_sub(1));
             self.update_zero_and_negative_flags(value);
         }
     };
@@ -142,6 +111,9 @@ macro_rules! set_flag {
     };
 }
 
+// XAA and LXA mix A with a chip-dependent constant, $EE matches most NES consoles
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
 fn page_cross(addr1: u16, addr2: u16) -> bool {
     addr1 & 0xFF00 != addr2 & 0xFF00
 }
@@ -159,9 +131,9 @@ impl<'a> CPU<'a> {
     compare!(cpx, x);
     compare!(cpy, y);
 
-    logical!(and, &);
-    logical!(eor, ^);
-    logical!(ora, |);
+    logical!(and, &=);
+    logical!(eor, ^=);
+    logical!(ora, |=);
 
     load_register!(lda, a);
     load_register!(ldx, x);
@@ -196,22 +168,8 @@ impl<'a> CPU<'a> {
     set_flag!(sei, INTERRUPT, true);
 
     pub fn adc(&mut self, mode: AddressMode) {
-        let (addr, page_cross) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
-
-        let carry = self.status.contains(StatusFlags::CARRY) as u16;
-        let sum = self.a as u16 + value as u16 + carry;
-        let result = sum as u8;
-
-        self.a = result;
-
-        self.update_zero_and_negative_flags(self.a);
-        self.update_carry_flag(sum);
-        self.update_overflow_flag(value, result);
-
-        if page_cross {
-            self.bus.tick(1);
-        }
+        let value = self.read_operand(mode);
+        self.add_with_carry(value);
     }
 
     pub fn asl_accumulator(&mut self) {
@@ -219,47 +177,49 @@ impl<'a> CPU<'a> {
         self.status.set(StatusFlags::CARRY, (value & 0x80) != 0);
         value <<= 1;
         self.a = value;
+        self.update_zero_and_negative_flags(self.a);
     }
 
     pub fn asl(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        let mut value = self.bus.mem_read(addr);
-        self.status.set(StatusFlags::CARRY, (value & 0x80) != 0);
-        value <<= 1;
-        self.bus.mem_write(addr, value);
+        let value = self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(StatusFlags::CARRY, (value & 0x80) != 0);
+            value << 1
+        });
         self.update_zero_and_negative_flags(value);
     }
 
     pub fn bit(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
+        let value = self.read_operand(mode);
         self.status.set(StatusFlags::ZERO, (self.a & value) == 0);
         self.status.set(StatusFlags::NEGATIVE, (value & 0x80) != 0);
         self.status.set(StatusFlags::OVERFLOW, (value & 0x40) != 0);
     }
 
     pub fn brk(&mut self) {
-        self.pc += 1;
-        self.push_word(self.pc); // Push PC + 2 to the stack
-        self.status.insert(StatusFlags::BREAK);
-        self.status.insert(StatusFlags::INTERRUPT);
-        self.push_status();
-
-        // Read the interrupt vector address from 0xFFFE/0xFFFF
-        let lo = self.bus.mem_read(0xFFFE) as u16;
-        let hi = self.bus.mem_read(0xFFFF) as u16;
-        self.pc = (hi << 8) | lo;
+        // BRK skips a padding byte, so the pushed return address is PC + 2
+        self.pc = self.pc.wrapping_add(1);
+        self.interrupt(interrupt::BRK);
     }
 
     pub fn jmp(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        self.pc = addr;
+        let addr = self.fetch_word();
+        self.pc = match mode {
+            AddressMode::Indirect => {
+                // the pointer high byte is fetched without carrying into the next page
+                let lo = self.read_byte(addr) as u16;
+                let hi = self.read_byte((addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF));
+                (hi as u16) << 8 | lo
+            }
+            _ => addr,
+        };
     }
 
     pub fn jsr(&mut self) {
-        let addr = self.fetch_word();
-        self.push_word(self.pc.wrapping_sub(1));
-        self.pc = addr;
+        let lo = self.fetch_byte() as u16;
+        self.read_byte(STACK + self.sp as u16);
+        self.push_word(self.pc);
+        let hi = self.fetch_byte() as u16;
+        self.pc = (hi << 8) | lo;
     }
 
     pub fn lsr_accumulator(&mut self) {
@@ -269,15 +229,11 @@ impl<'a> CPU<'a> {
     }
 
     pub fn lsr(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
-
-        let result = value >> 1;
-
-        self.bus.mem_write(addr, result);
-        self.status.set(StatusFlags::CARRY, value & 0x01 != 0);
-        self.status.set(StatusFlags::ZERO, result == 0);
-        self.status.set(StatusFlags::NEGATIVE, false);
+        let value = self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(StatusFlags::CARRY, value & 0x01 != 0);
+            value >> 1
+        });
+        self.update_zero_and_negative_flags(value);
     }
 
     pub fn nop(&mut self) {}
@@ -291,17 +247,20 @@ impl<'a> CPU<'a> {
     }
 
     pub fn pla(&mut self) {
+        self.read_byte(STACK + self.sp as u16);
         self.a = self.pop();
         self.update_zero_and_negative_flags(self.a);
     }
 
     pub fn plp(&mut self) {
+        self.read_byte(STACK + self.sp as u16);
         self.status = StatusFlags::from_bits_truncate(self.pop());
         // Clear the BREAK and UNUSED flags
         self.status.remove(StatusFlags::BREAK | StatusFlags::UNUSED);
     }
 
     pub fn rti(&mut self) {
+        self.read_byte(STACK + self.sp as u16);
         self.status = StatusFlags::from_bits_truncate(self.pop());
         self.status.remove(StatusFlags::BREAK | StatusFlags::UNUSED);
 
@@ -311,145 +270,307 @@ impl<'a> CPU<'a> {
     }
 
     pub fn rts(&mut self) {
+        self.read_byte(STACK + self.sp as u16);
         let pcl = self.pop() as u16;
         let pch = self.pop() as u16;
         self.pc = (pch << 8) | pcl;
-        self.pc += 1;
+        self.fetch_byte();
     }
 
     pub fn rol(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
-        let result = self.rotate_left(value);
-        self.bus.mem_write(addr, result);
-        self.update_zero_and_negative_flags(result);
+        let value = self.read_modify_write(mode, |cpu, value| cpu.rotate_left(value));
+        self.update_zero_and_negative_flags(value);
     }
 
     pub fn ror(&mut self, mode: AddressMode) {
-        let (addr, _) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
-        let result = self.rotate_right(value);
-        self.bus.mem_write(addr, result);
-        self.update_zero_and_negative_flags(result);
+        let value = self.read_modify_write(mode, |cpu, value| cpu.rotate_right(value));
+        self.update_zero_and_negative_flags(value);
     }
 
     pub fn rol_accumulator(&mut self) {
-        let carry = if self.status.contains(StatusFlags::CARRY) {
-            1
-        } else {
-            0
-        };
-        let result = (self.a << 1) | carry;
-        self.status.set(StatusFlags::CARRY, self.a & 0x80 != 0);
-        self.a = result;
+        self.a = self.rotate_left(self.a);
         self.update_zero_and_negative_flags(self.a);
     }
 
     pub fn ror_accumulator(&mut self) {
-        let carry = if self.status.contains(StatusFlags::CARRY) {
-            0x80
-        } else {
-            0
-        };
-        let result = (self.a >> 1) | carry;
-        self.status.set(StatusFlags::CARRY, self.a & 0x01 != 0);
-        self.a = result;
+        self.a = self.rotate_right(self.a);
         self.update_zero_and_negative_flags(self.a);
     }
 
     pub fn sbc(&mut self, mode: AddressMode) {
-        let (addr, page_cross) = self.get_operand(mode);
-        let value = self.bus.mem_read(addr);
+        let value = self.read_operand(mode);
+        // A - M - (1 - C) is the same as A + !M + C
+        self.add_with_carry(!value);
+    }
+
+    pub fn txs(&mut self) {
+        self.sp = self.x;
+    }
 
-        let carry = !self.status.contains(StatusFlags::CARRY) as u8;
-        let result = self.a.wrapping_sub(value).wrapping_sub(carry);
+    /* unofficial */
 
-        self.status
-            .set(StatusFlags::CARRY, self.a >= (value + carry));
-        self.status.set(StatusFlags::ZERO, result == 0);
-        self.status.set(StatusFlags::NEGATIVE, result & 0x80 != 0);
+    pub fn kil(&mut self) {
+        // the CPU jams: keep re-executing this opcode until reset
+        self.pc = self.pc.wrapping_sub(1);
+    }
 
-        let overflow = ((self.a ^ result) & 0x80 != 0) && ((self.a ^ value) & 0x80 != 0);
-        self.status.set(StatusFlags::OVERFLOW, overflow);
+    pub fn nop_read(&mut self, mode: AddressMode) {
+        self.read_operand(mode);
+    }
 
-        self.a = result;
+    pub fn lax(&mut self, mode: AddressMode) {
+        let value = self.read_operand(mode);
+        self.a = value;
+        self.x = value;
+        self.update_zero_and_negative_flags(value);
+    }
 
-        if page_cross {
-            self.bus.tick(1);
-        }
+    pub fn sax(&mut self, mode: AddressMode) {
+        let addr = self.get_operand_address(mode, true);
+        self.write_byte(addr, self.a & self.x);
     }
 
-    pub fn txs(&mut self) {
-        self.sp = self.x;
+    pub fn dcp(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_sub(1));
+        self.compare(self.a, value);
+    }
+
+    pub fn isb(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_add(1));
+        self.add_with_carry(!value);
+    }
+
+    pub fn slo(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(StatusFlags::CARRY, value & 0x80 != 0);
+            value << 1
+        });
+        self.a |= value;
+        self.update_zero_and_negative_flags(self.a);
     }
 
-    pub fn get_operand(&mut self, mode: AddressMode) -> (u16, bool) {
+    pub fn rla(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |cpu, value| cpu.rotate_left(value));
+        self.a &= value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    pub fn sre(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(StatusFlags::CARRY, value & 0x01 != 0);
+            value >> 1
+        });
+        self.a ^= value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    pub fn rra(&mut self, mode: AddressMode) {
+        let value = self.read_modify_write(mode, |cpu, value| cpu.rotate_right(value));
+        self.add_with_carry(value);
+    }
+
+    pub fn anc(&mut self, mode: AddressMode) {
+        self.and(mode);
+        self.status.set(
+            StatusFlags::CARRY,
+            self.status.contains(StatusFlags::NEGATIVE),
+        );
+    }
+
+    pub fn alr(&mut self, mode: AddressMode) {
+        self.and(mode);
+        self.lsr_accumulator();
+    }
+
+    pub fn arr(&mut self, mode: AddressMode) {
+        self.and(mode);
+        self.ror_accumulator();
+        // C and V come from the adder, not from the rotation
+        self.status.set(StatusFlags::CARRY, self.a & 0x40 != 0);
+        self.status.set(
+            StatusFlags::OVERFLOW,
+            ((self.a >> 6) ^ (self.a >> 5)) & 1 != 0,
+        );
+    }
+
+    pub fn axs(&mut self, mode: AddressMode) {
+        let value = self.read_operand(mode);
+        let and = self.a & self.x;
+        self.x = and.wrapping_sub(value);
+        self.status.set(StatusFlags::CARRY, and >= value);
+        self.update_zero_and_negative_flags(self.x);
+    }
+
+    pub fn las(&mut self, mode: AddressMode) {
+        let value = self.read_operand(mode) & self.sp;
+        self.a = value;
+        self.x = value;
+        self.sp = value;
+        self.update_zero_and_negative_flags(value);
+    }
+
+    pub fn xaa(&mut self, mode: AddressMode) {
+        let value = self.read_operand(mode);
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & value;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    pub fn lxa(&mut self, mode: AddressMode) {
+        let value = self.read_operand(mode);
+        self.a = (self.a | UNSTABLE_MAGIC) & value;
+        self.x = self.a;
+        self.update_zero_and_negative_flags(self.a);
+    }
+
+    pub fn tas(&mut self, mode: AddressMode) {
+        self.sp = self.a & self.x;
+        self.store_and_high_byte(mode, self.sp, self.y);
+    }
+
+    pub fn ahx(&mut self, mode: AddressMode) {
+        self.store_and_high_byte(mode, self.a & self.x, self.y);
+    }
+
+    pub fn shx(&mut self, mode: AddressMode) {
+        self.store_and_high_byte(mode, self.x, self.y);
+    }
+
+    pub fn shy(&mut self, mode: AddressMode) {
+        self.store_and_high_byte(mode, self.y, self.x);
+    }
+
+    // SHX, SHY, AHX and TAS store `value & (H + 1)`, where H is the high byte of the
+    // unindexed address. When indexing crosses a page that same value replaces the
+    // high byte of the target address.
+    fn store_and_high_byte(&mut self, mode: AddressMode, value: u8, index: u8) {
+        let addr = self.get_operand_address(mode, true);
+        let base = addr.wrapping_sub(index as u16);
+        let result = value & ((base >> 8) as u8).wrapping_add(1);
+
+        let addr = if page_cross(base, addr) {
+            ((result as u16) << 8) | (addr & 0x00FF)
+        } else {
+            addr
+        };
+        self.write_byte(addr, result);
+    }
+
+    /// Fetches the operand bytes for `mode` and returns the effective address, spending
+    /// the same bus cycles as the real addressing sequence. Indexed modes re-read from
+    /// the address before the page carry is fixed up when the index crosses a page;
+    /// stores and read-modify-write instructions always pay for that read.
+    pub fn get_operand_address(&mut self, mode: AddressMode, write: bool) -> u16 {
         match mode {
-            AddressMode::Immediate => (self.pc, false),
-            AddressMode::ZeroPage => (self.bus.mem_read(self.pc) as u16, false),
+            AddressMode::Immediate => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                addr
+            }
+            AddressMode::ZeroPage => self.fetch_byte() as u16,
             AddressMode::ZeroPageX => {
-                let pos = self.bus.mem_read(self.pc);
-                let addr = pos.wrapping_add(self.x) as u16;
-                (addr, false)
+                let pos = self.fetch_byte();
+                self.read_byte(pos as u16);
+                pos.wrapping_add(self.x) as u16
             }
             AddressMode::ZeroPageY => {
-                let pos = self.bus.mem_read(self.pc);
-                let addr = pos.wrapping_add(self.x) as u16;
-                (addr, false)
+                let pos = self.fetch_byte();
+                self.read_byte(pos as u16);
+                pos.wrapping_add(self.y) as u16
             }
-            AddressMode::Absolute => (self.bus.read_word(self.pc), false),
+            AddressMode::Absolute => self.fetch_word(),
             AddressMode::AbsoluteX => {
-                let base = self.bus.read_word(self.pc);
-                let addr = base.wrapping_add(self.x as u16);
-                (addr, page_cross(base, addr))
+                let base = self.fetch_word();
+                self.index_address(base, self.x, write)
             }
             AddressMode::AbsoluteY => {
-                let base = self.bus.read_word(self.pc);
-                let addr = base.wrapping_add(self.y as u16);
-                (addr, page_cross(base, addr))
+                let base = self.fetch_word();
+                self.index_address(base, self.y, write)
             }
             AddressMode::IndirectX => {
-                let base = self.bus.mem_read(self.pc);
+                let base = self.fetch_byte();
+                self.read_byte(base as u16);
 
-                let ptr: u8 = (base as u8).wrapping_add(self.x);
-                let lo = self.bus.mem_read(ptr as u16);
-                let hi = self.bus.mem_read(ptr.wrapping_add(1) as u16);
-                ((hi as u16) << 8 | (lo as u16), false)
+                let ptr: u8 = base.wrapping_add(self.x);
+                let lo = self.read_byte(ptr as u16);
+                let hi = self.read_byte(ptr.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
             }
             AddressMode::IndirectY => {
-                let base = self.bus.mem_read(self.pc);
+                let base = self.fetch_byte();
 
-                let lo = self.bus.mem_read(base as u16);
-                let hi = self.bus.mem_read((base as u8).wrapping_add(1) as u16);
+                let lo = self.read_byte(base as u16);
+                let hi = self.read_byte(base.wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
-                let deref = deref_base.wrapping_add(self.y as u16);
-                (deref, page_cross(deref, deref_base))
+                self.index_address(deref_base, self.y, write)
             }
             _ => panic!("Addressing mode not supported"),
         }
     }
 
-    fn update_zero_and_negative_flags(&mut self, value: u8) {
-        self.status.set(StatusFlags::ZERO, value == 0);
-        self.status.set(StatusFlags::NEGATIVE, value & 0x80 != 0);
+    fn index_address(&mut self, base: u16, index: u8, write: bool) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        if write || page_cross(base, addr) {
+            self.read_byte((base & 0xFF00) | (addr & 0x00FF));
+        }
+        addr
     }
 
-    fn update_carry_flag(&mut self, sum: u16) {
-        if sum > 0xFF {
-            self.status.insert(StatusFlags::CARRY);
-        } else {
-            self.status.remove(StatusFlags::CARRY);
-        }
+    fn read_operand(&mut self, mode: AddressMode) -> u8 {
+        let addr = self.get_operand_address(mode, false);
+        self.read_byte(addr)
+    }
+
+    // the unmodified value is written back while the ALU computes the result
+    fn read_modify_write<F>(&mut self, mode: AddressMode, modify: F) -> u8
+    where
+        F: FnOnce(&mut Self, u8) -> u8,
+    {
+        let addr = self.get_operand_address(mode, true);
+        let value = self.read_byte(addr);
+        self.write_byte(addr, value);
+        let result = modify(self, value);
+        self.write_byte(addr, result);
+        result
     }
 
-    fn update_overflow_flag(&mut self, value: u8, result: u8) {
-        let overflow = ((self.a ^ value) & StatusFlags::NEGATIVE.bits() == 0)
-            && ((self.a ^ result) & StatusFlags::NEGATIVE.bits() != 0);
-        if overflow {
-            self.status.insert(StatusFlags::OVERFLOW);
+    fn branch(&mut self, jump: i8) {
+        // a taken branch that stays on its page does not poll interrupts on the
+        // last cycle, so a pending IRQ or NMI waits for one more instruction
+        let polled = (self.nmi_pending, self.irq_pending);
+        self.read_byte(self.pc);
+
+        let jump_addr = self.pc.wrapping_add(jump as u16);
+        if page_cross(self.pc, jump_addr) {
+            self.read_byte((self.pc & 0xFF00) | (jump_addr & 0x00FF));
         } else {
-            self.status.remove(StatusFlags::OVERFLOW);
+            (self.nmi_pending, self.irq_pending) = polled;
         }
+        self.pc = jump_addr;
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        let result = register.wrapping_sub(value);
+        self.status.set(StatusFlags::CARRY, register >= value);
+        self.update_zero_and_negative_flags(result);
+    }
+
+    fn update_zero_and_negative_flags(&mut self, value: u8) {
+        self.status.set(StatusFlags::ZERO, value == 0);
+        self.status.set(StatusFlags::NEGATIVE, value & 0x80 != 0);
+    }
+
+    fn add_with_carry(&mut self, value: u8) {
+        let carry = self.status.contains(StatusFlags::CARRY) as u16;
+        let sum = self.a as u16 + value as u16 + carry;
+        let result = sum as u8;
+
+        // overflow when both operands share a sign that differs from the result
+        let overflow = (self.a ^ result) & (value ^ result) & 0x80 != 0;
+        self.status.set(StatusFlags::CARRY, sum > 0xFF);
+        self.status.set(StatusFlags::OVERFLOW, overflow);
+
+        self.a = result;
+        self.update_zero_and_negative_flags(self.a);
     }
 
     fn rotate_right(&mut self, value: u8) -> u8 {
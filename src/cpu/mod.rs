@@ -1,19 +1,26 @@
 pub mod instructions;
 pub mod opcodes;
+pub mod trace;
 
 use crate::bus::Bus;
+use crate::debugger::{Access, AccessKind, AddressSpace};
+use crate::savestate::{Chunks, Snapshot, StateReader, StateWriter};
 use std::collections::HashMap;
 
 use bitflags::bitflags;
 use instructions::*;
+use trace::Tracer;
 
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xFD;
 
+pub use interrupt::InterruptType;
+
 mod interrupt {
-    #[derive(PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum InterruptType {
         NMI,
+        IRQ,
         BRK,
     }
 
@@ -22,21 +29,29 @@ mod interrupt {
         pub(super) itype: InterruptType,
         pub(super) vector_addr: u16,
         pub(super) b_flag_mask: u8,
-        pub(super) cpu_cycles: u8,
+        pub(super) dummy_reads: u8,
     }
 
     pub(super) const NMI: Interrupt = Interrupt {
         itype: InterruptType::NMI,
         vector_addr: 0xFFFA,
         b_flag_mask: 0b00100000,
-        cpu_cycles: 2,
+        dummy_reads: 2,
     };
 
+    pub(super) const IRQ: Interrupt = Interrupt {
+        itype: InterruptType::IRQ,
+        vector_addr: 0xFFFE,
+        b_flag_mask: 0b00100000,
+        dummy_reads: 2,
+    };
+
+    // BRK reads its padding byte as an instruction operand instead
     pub(super) const BRK: Interrupt = Interrupt {
         itype: InterruptType::BRK,
         vector_addr: 0xFFFE,
         b_flag_mask: 0b00110000,
-        cpu_cycles: 1,
+        dummy_reads: 0,
     };
 }
 
@@ -61,43 +76,108 @@ pub struct CPU<'a> {
     pub sp: u8,
     pub status: StatusFlags,
     pub bus: Bus<'a>,
+    // interrupt lines as sampled at the start of the current cycle, so that at the
+    // end of an instruction they hold what the CPU saw on its second-to-last cycle
+    nmi_pending: bool,
+    irq_pending: bool,
+    tracer: Option<Tracer>,
+    accesses: Option<Vec<Access>>,
 }
 
 impl<'a> CPU<'a> {
     pub fn new<'b>(bus: Bus<'b>) -> CPU<'b> {
-        return CPU {
+        CPU {
             a: 0,
             x: 0,
             y: 0,
             pc: 0,
             sp: STACK_RESET,
             status: StatusFlags::from_bits_truncate(0b100100),
-            bus: bus,
-        };
+            bus,
+            nmi_pending: false,
+            irq_pending: false,
+            tracer: None,
+            accesses: None,
+        }
     }
 
     pub fn reset(&mut self) {
         self.a = 0;
         self.x = 0;
         self.y = 0;
-        self.sp = STACK_RESET;
         self.status = StatusFlags::from_bits_truncate(0b100100);
 
+        // reset runs the 7 cycle interrupt sequence with its three pushes turned
+        // into reads, which is what leaves SP at $FD
+        self.sp = STACK_RESET.wrapping_add(3);
+        self.read_byte(self.pc);
+        self.read_byte(self.pc);
+        for _ in 0..3 {
+            self.read_byte(STACK + self.sp as u16);
+            self.sp = self.sp.wrapping_sub(1);
+        }
         self.pc = self.read_reset_vector();
+
+        self.nmi_pending = false;
+        self.irq_pending = false;
     }
 
     fn read_reset_vector(&mut self) -> u16 {
-        self.bus.read_word(0xFFFC)
+        let lo = self.read_byte(0xFFFC) as u16;
+        let hi = self.read_byte(0xFFFD) as u16;
+        (hi << 8) | lo
+    }
+
+    /// Snapshots the whole machine: registers, RAM, PPU, APU, controllers and the
+    /// cartridge's registers and RAM. The tracer and debugger hooks are not saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.chunk(*b"CPU ", |state| self.save(state));
+        self.bus.save_chunks(&mut state);
+        state.finish()
+    }
+
+    /// Restores a state written by `save_state`. Nothing is changed if the state is
+    /// malformed or from an unsupported version.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let chunks = Chunks::parse(data)?;
+        chunks.load(*b"CPU ", self);
+        self.bus.load_chunks(&chunks);
+        Ok(())
+    }
+
+    /// Logs every following instruction through `tracer`, replacing any previous one.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Starts or stops recording every bus access for `drain_accesses`.
+    pub fn record_accesses(&mut self, enabled: bool) {
+        self.accesses = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Hands out the accesses recorded since the last call. Reads and writes of
+    /// PPUDATA also show up as accesses to the PPU address space.
+    pub fn drain_accesses(&mut self) -> impl Iterator<Item = Access> + '_ {
+        self.accesses.iter_mut().flat_map(|log| log.drain(..))
     }
 
     pub fn push(&mut self, value: u8) {
-        self.bus.mem_write((STACK as u16) + self.sp as u16, value);
+        self.write_byte(STACK + self.sp as u16, value);
         self.sp = self.sp.wrapping_sub(1);
     }
 
     pub fn pop(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        self.bus.mem_read((STACK as u16) + self.sp as u16)
+        self.read_byte(STACK + self.sp as u16)
     }
 
     pub fn push_status(&mut self) {
@@ -113,14 +193,19 @@ impl<'a> CPU<'a> {
         self.push(lo);
     }
 
+    /// Reads the next opcode or operand byte.
     pub fn fetch_byte(&mut self) -> u8 {
-        let byte = self.bus.mem_read(self.pc);
-        self.pc += 1;
+        let byte = self.bus_access(AccessKind::Execute, self.pc, 0);
+        self.pc = self.pc.wrapping_add(1);
         byte
     }
 
     pub fn read_byte(&mut self, addr: u16) -> u8 {
-        self.bus.mem_read(addr)
+        self.bus_access(AccessKind::Read, addr, 0)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, data: u8) {
+        self.bus_access(AccessKind::Write, addr, data);
     }
 
     pub fn fetch_word(&mut self) -> u16 {
@@ -129,11 +214,58 @@ impl<'a> CPU<'a> {
         (hi << 8) | lo
     }
 
+    /// Every CPU cycle is exactly one bus access, so this is where the rest of the
+    /// system is clocked.
+    fn bus_access(&mut self, kind: AccessKind, addr: u16, data: u8) -> u8 {
+        self.tick();
+
+        // PPUDATA reaches into the PPU address space at the current VRAM address
+        let ppu_addr = match addr {
+            0x2000..=0x3FFF if addr & 0b111 == 7 && self.accesses.is_some() => {
+                Some(self.bus.ppu.registers.scroll.addr() & 0x3FFF)
+            }
+            _ => None,
+        };
+
+        let data = match kind {
+            AccessKind::Write => {
+                self.bus.mem_write(addr, data);
+                data
+            }
+            AccessKind::Read | AccessKind::Execute => self.bus.mem_read(addr),
+        };
+
+        if let Some(log) = self.accesses.as_mut() {
+            log.push(Access {
+                space: AddressSpace::Cpu,
+                kind,
+                addr,
+                data,
+            });
+            if let Some(ppu_addr) = ppu_addr {
+                let (kind, data) = match kind {
+                    AccessKind::Write => (AccessKind::Write, data),
+                    _ => (AccessKind::Read, self.bus.ppu.peek(ppu_addr)),
+                };
+                log.push(Access {
+                    space: AddressSpace::Ppu,
+                    kind,
+                    addr: ppu_addr,
+                    data,
+                });
+            }
+        }
+        data
+    }
+
+    fn tick(&mut self) {
+        self.nmi_pending = self.bus.ppu.nmi_pending();
+        self.irq_pending = self.bus.irq_pending() && !self.status.contains(StatusFlags::INTERRUPT);
+        self.bus.tick(1);
+    }
+
     pub fn nmi(&mut self) {
-        self.push_word(self.pc);
-        self.push_status();
-        self.status.insert(StatusFlags::INTERRUPT);
-        self.pc = self.bus.read_word(0xFFFA);
+        self.interrupt(interrupt::NMI);
     }
 
     fn execute_opcode(&mut self, opcode: u8) {
@@ -190,9 +322,9 @@ impl<'a> CPU<'a> {
             0xBE => self.ldx(AddressMode::AbsoluteY),
             0xA0 => self.ldy(AddressMode::Immediate),
             0xA4 => self.ldy(AddressMode::ZeroPage),
-            0xB4 => self.ldy(AddressMode::ZeroPageY),
+            0xB4 => self.ldy(AddressMode::ZeroPageX),
             0xAC => self.ldy(AddressMode::Absolute),
-            0xBC => self.ldy(AddressMode::AbsoluteY),
+            0xBC => self.ldy(AddressMode::AbsoluteX),
 
             // ASL
             0x0A => self.asl_accumulator(),
@@ -333,39 +465,229 @@ impl<'a> CPU<'a> {
             0x8A => self.txa(),
             0x9A => self.txs(),
             0x98 => self.tya(),
-            _ => self.nop(),
+
+            // *DCP
+            0xC7 => self.dcp(AddressMode::ZeroPage),
+            0xD7 => self.dcp(AddressMode::ZeroPageX),
+            0xCF => self.dcp(AddressMode::Absolute),
+            0xDF => self.dcp(AddressMode::AbsoluteX),
+            0xDB => self.dcp(AddressMode::AbsoluteY),
+            0xD3 => self.dcp(AddressMode::IndirectY),
+            0xC3 => self.dcp(AddressMode::IndirectX),
+
+            // *RLA
+            0x27 => self.rla(AddressMode::ZeroPage),
+            0x37 => self.rla(AddressMode::ZeroPageX),
+            0x2F => self.rla(AddressMode::Absolute),
+            0x3F => self.rla(AddressMode::AbsoluteX),
+            0x3B => self.rla(AddressMode::AbsoluteY),
+            0x33 => self.rla(AddressMode::IndirectY),
+            0x23 => self.rla(AddressMode::IndirectX),
+
+            // *SLO
+            0x07 => self.slo(AddressMode::ZeroPage),
+            0x17 => self.slo(AddressMode::ZeroPageX),
+            0x0F => self.slo(AddressMode::Absolute),
+            0x1F => self.slo(AddressMode::AbsoluteX),
+            0x1B => self.slo(AddressMode::AbsoluteY),
+            0x03 => self.slo(AddressMode::IndirectX),
+            0x13 => self.slo(AddressMode::IndirectY),
+
+            // *SRE
+            0x47 => self.sre(AddressMode::ZeroPage),
+            0x57 => self.sre(AddressMode::ZeroPageX),
+            0x4F => self.sre(AddressMode::Absolute),
+            0x5F => self.sre(AddressMode::AbsoluteX),
+            0x5B => self.sre(AddressMode::AbsoluteY),
+            0x43 => self.sre(AddressMode::IndirectX),
+            0x53 => self.sre(AddressMode::IndirectY),
+
+            // *NOP
+            0x80 => self.nop_read(AddressMode::Immediate),
+            0x82 => self.nop_read(AddressMode::Immediate),
+            0x89 => self.nop_read(AddressMode::Immediate),
+            0xC2 => self.nop_read(AddressMode::Immediate),
+            0xE2 => self.nop_read(AddressMode::Immediate),
+            0x04 => self.nop_read(AddressMode::ZeroPage),
+            0x44 => self.nop_read(AddressMode::ZeroPage),
+            0x64 => self.nop_read(AddressMode::ZeroPage),
+            0x14 => self.nop_read(AddressMode::ZeroPageX),
+            0x34 => self.nop_read(AddressMode::ZeroPageX),
+            0x54 => self.nop_read(AddressMode::ZeroPageX),
+            0x74 => self.nop_read(AddressMode::ZeroPageX),
+            0xD4 => self.nop_read(AddressMode::ZeroPageX),
+            0xF4 => self.nop_read(AddressMode::ZeroPageX),
+            0x0C => self.nop_read(AddressMode::Absolute),
+            0x1C => self.nop_read(AddressMode::AbsoluteX),
+            0x3C => self.nop_read(AddressMode::AbsoluteX),
+            0x5C => self.nop_read(AddressMode::AbsoluteX),
+            0x7C => self.nop_read(AddressMode::AbsoluteX),
+            0xDC => self.nop_read(AddressMode::AbsoluteX),
+            0xFC => self.nop_read(AddressMode::AbsoluteX),
+            0x1A => self.nop(),
+            0x3A => self.nop(),
+            0x5A => self.nop(),
+            0x7A => self.nop(),
+            0xDA => self.nop(),
+            0xFA => self.nop(),
+
+            // *AXS
+            0xCB => self.axs(AddressMode::Immediate),
+
+            // *ARR
+            0x6B => self.arr(AddressMode::Immediate),
+
+            // *SBC
+            0xEB => self.sbc(AddressMode::Immediate),
+
+            // *ANC
+            0x0B => self.anc(AddressMode::Immediate),
+            0x2B => self.anc(AddressMode::Immediate),
+
+            // *ALR
+            0x4B => self.alr(AddressMode::Immediate),
+
+            // *RRA
+            0x67 => self.rra(AddressMode::ZeroPage),
+            0x77 => self.rra(AddressMode::ZeroPageX),
+            0x6F => self.rra(AddressMode::Absolute),
+            0x7F => self.rra(AddressMode::AbsoluteX),
+            0x7B => self.rra(AddressMode::AbsoluteY),
+            0x63 => self.rra(AddressMode::IndirectX),
+            0x73 => self.rra(AddressMode::IndirectY),
+
+            // *ISB
+            0xE7 => self.isb(AddressMode::ZeroPage),
+            0xF7 => self.isb(AddressMode::ZeroPageX),
+            0xEF => self.isb(AddressMode::Absolute),
+            0xFF => self.isb(AddressMode::AbsoluteX),
+            0xFB => self.isb(AddressMode::AbsoluteY),
+            0xE3 => self.isb(AddressMode::IndirectX),
+            0xF3 => self.isb(AddressMode::IndirectY),
+
+            // *KIL
+            0x02 => self.kil(),
+            0x12 => self.kil(),
+            0x22 => self.kil(),
+            0x32 => self.kil(),
+            0x42 => self.kil(),
+            0x52 => self.kil(),
+            0x62 => self.kil(),
+            0x72 => self.kil(),
+            0x92 => self.kil(),
+            0xB2 => self.kil(),
+            0xD2 => self.kil(),
+            0xF2 => self.kil(),
+
+            // *LXA
+            0xAB => self.lxa(AddressMode::Immediate),
+
+            // *XAA
+            0x8B => self.xaa(AddressMode::Immediate),
+
+            // *LAS
+            0xBB => self.las(AddressMode::AbsoluteY),
+
+            // *TAS
+            0x9B => self.tas(AddressMode::AbsoluteY),
+
+            // *AHX
+            0x93 => self.ahx(AddressMode::IndirectY),
+            0x9F => self.ahx(AddressMode::AbsoluteY),
+
+            // *SHX
+            0x9E => self.shx(AddressMode::AbsoluteY),
+
+            // *SHY
+            0x9C => self.shy(AddressMode::AbsoluteX),
+
+            // *LAX
+            0xA7 => self.lax(AddressMode::ZeroPage),
+            0xB7 => self.lax(AddressMode::ZeroPageY),
+            0xAF => self.lax(AddressMode::Absolute),
+            0xBF => self.lax(AddressMode::AbsoluteY),
+            0xA3 => self.lax(AddressMode::IndirectX),
+            0xB3 => self.lax(AddressMode::IndirectY),
+
+            // *SAX
+            0x87 => self.sax(AddressMode::ZeroPage),
+            0x97 => self.sax(AddressMode::ZeroPageY),
+            0x8F => self.sax(AddressMode::Absolute),
+            0x83 => self.sax(AddressMode::IndirectX),
         }
     }
 
     fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        for _ in 0..interrupt.dummy_reads {
+            self.read_byte(self.pc);
+        }
+
         self.push_word(self.pc);
-        let mut flag = self.status.clone();
-        flag.set(StatusFlags::BREAK, interrupt.b_flag_mask & 0b010000 == 1);
-        flag.set(StatusFlags::UNUSED, interrupt.b_flag_mask & 0b100000 == 1);
+        let mut flag = self.status;
+        flag.set(StatusFlags::BREAK, interrupt.b_flag_mask & 0b010000 != 0);
+        flag.set(StatusFlags::UNUSED, interrupt.b_flag_mask & 0b100000 != 0);
 
         self.push(flag.bits);
         self.status.insert(StatusFlags::INTERRUPT);
 
-        self.bus.tick(interrupt.cpu_cycles);
-        self.pc = self.bus.read_word(interrupt.vector_addr);
+        // an NMI asserted before the vector fetch hijacks a BRK or IRQ sequence,
+        // which then jumps through $FFFA while keeping its pushed B flag
+        let vector_addr = if interrupt.itype != interrupt::InterruptType::NMI
+            && self.bus.ppu.poll_nmi_interrupt().is_some()
+        {
+            interrupt::NMI.vector_addr
+        } else {
+            interrupt.vector_addr
+        };
+
+        let lo = self.read_byte(vector_addr) as u16;
+        let hi = self.read_byte(vector_addr.wrapping_add(1)) as u16;
+        self.pc = (hi << 8) | lo;
+
+        // the line that was just serviced must not be taken again
+        self.nmi_pending = false;
+        self.irq_pending = false;
+    }
+
+    /// Services a pending NMI or IRQ. NMI is edge-triggered and always taken, IRQ is
+    /// level-triggered and masked by the I flag. Both are sampled on the
+    /// second-to-last cycle of the previous instruction, which is why CLI, SEI and
+    /// PLP only take effect after the next instruction.
+    ///
+    /// `step` does this on its own; calling it first leaves the CPU at the start of
+    /// the handler, which is where a debugger wants to stop.
+    pub fn service_interrupts(&mut self) -> Option<InterruptType> {
+        if self.nmi_pending {
+            self.bus.ppu.poll_nmi_interrupt();
+            self.interrupt(interrupt::NMI);
+            Some(InterruptType::NMI)
+        } else if self.irq_pending {
+            self.interrupt(interrupt::IRQ);
+            Some(InterruptType::IRQ)
+        } else {
+            None
+        }
     }
 
     pub fn step(&mut self) {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
+        let opcodes: &HashMap<u8, &'static opcodes::OpCode> = &opcodes::OPCODES_MAP;
 
-        let opcode = opcodes
-            .get(&self.fetch_byte())
-            .expect(&format!("Opcode is not recognized"));
+        self.service_interrupts();
 
-        let pc_state = self.pc;
-        println!("{:#02X} {}", self.pc, opcode.mnemonic);
-        self.execute_opcode(opcode.code);
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.log(self);
+            self.tracer = Some(tracer);
+        }
 
-        self.bus.tick(opcode.cycles as u8);
+        let code = self.fetch_byte();
+        let opcode = opcodes.get(&code).expect("Opcode is not recognized");
 
-        if pc_state == self.pc {
-            self.pc += (opcode.len - 1) as u16;
+        // single byte instructions still read the following byte on their second cycle
+        if opcode.len == 1 {
+            self.read_byte(self.pc);
         }
+
+        self.execute_opcode(opcode.code);
     }
 
     pub fn run(&mut self) {
@@ -376,28 +698,33 @@ impl<'a> CPU<'a> {
     where
         F: FnMut(&mut CPU),
     {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
-
         loop {
-            if let Some(_nmi) = self.bus.ppu.poll_nmi_interrupt() {
-                self.interrupt(interrupt::NMI);
-            }
-
             callback(self);
+            self.step();
+        }
+    }
+}
 
-            let opcode = opcodes
-                .get(&self.fetch_byte())
-                .expect(&format!("Opcode is not recognized"));
-
-            let pc_state = self.pc;
-            println!("{:#02X} {}", self.pc, opcode.mnemonic);
-            self.execute_opcode(opcode.code);
-
-            self.bus.tick(opcode.cycles as u8);
+impl Snapshot for CPU<'_> {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.a);
+        state.u8(self.x);
+        state.u8(self.y);
+        state.u16(self.pc);
+        state.u8(self.sp);
+        state.u8(self.status.bits());
+        state.bool(self.nmi_pending);
+        state.bool(self.irq_pending);
+    }
 
-            if pc_state == self.pc {
-                self.pc += (opcode.len - 1) as u16;
-            }
-        }
+    fn load(&mut self, state: &mut StateReader) {
+        self.a = state.u8();
+        self.x = state.u8();
+        self.y = state.u8();
+        self.pc = state.u16();
+        self.sp = state.u8();
+        self.status = StatusFlags::from_bits_truncate(state.u8());
+        self.nmi_pending = state.bool();
+        self.irq_pending = state.bool();
     }
 }
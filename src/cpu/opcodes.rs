@@ -14,11 +14,11 @@ pub struct OpCode {
 impl OpCode {
     fn new(code: u8, mnemonic: &'static str, len: u8, cycles: u8, mode: AddressMode) -> Self {
         OpCode {
-            code: code,
-            mnemonic: mnemonic,
-            len: len,
-            cycles: cycles,
-            mode: mode,
+            code,
+            mnemonic,
+            len,
+            cycles,
+            mode,
         }
     }
 }
@@ -225,7 +225,7 @@ lazy_static! {
         OpCode::new(0xc7, "*DCP", 2, 5, AddressMode::ZeroPage),
         OpCode::new(0xd7, "*DCP", 2, 6, AddressMode::ZeroPageX),
         OpCode::new(0xCF, "*DCP", 3, 6, AddressMode::Absolute),
-        OpCode::new(0xdF, "*DCP", 3, 7, AddressMode::AbsoluteX),
+        OpCode::new(0xdf, "*DCP", 3, 7, AddressMode::AbsoluteX),
         OpCode::new(0xdb, "*DCP", 3, 7, AddressMode::AbsoluteY),
         OpCode::new(0xd3, "*DCP", 2, 8, AddressMode::IndirectY),
         OpCode::new(0xc3, "*DCP", 2, 8, AddressMode::IndirectX),
@@ -309,18 +309,18 @@ lazy_static! {
         OpCode::new(0xe3, "*ISB", 2,8, AddressMode::IndirectX),
         OpCode::new(0xf3, "*ISB", 2,8, AddressMode::IndirectY),
 
-        OpCode::new(0x02, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x12, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x22, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x32, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x42, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x52, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x62, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x72, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0x92, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0xb2, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0xd2, "*NOP", 1,2, AddressMode::None),
-        OpCode::new(0xf2, "*NOP", 1,2, AddressMode::None),
+        OpCode::new(0x02, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x12, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x22, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x32, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x42, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x52, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x62, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x72, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0x92, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0xb2, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0xd2, "*KIL", 1,2, AddressMode::None),
+        OpCode::new(0xf2, "*KIL", 1,2, AddressMode::None),
 
         OpCode::new(0x1a, "*NOP", 1,2, AddressMode::None),
         OpCode::new(0x3a, "*NOP", 1,2, AddressMode::None),
@@ -330,22 +330,23 @@ lazy_static! {
         // OpCode::new(0xea, "NOP", 1,2, AddressMode::None),
         OpCode::new(0xfa, "*NOP", 1,2, AddressMode::None),
 
-        OpCode::new(0xab, "*LXA", 2, 3, AddressMode::Immediate), //todo: highly unstable and not used
-        //http://visual6502.org/wiki/index.php?title=6502_Opcode_8B_%28XAA,_ANE%29
-        OpCode::new(0x8b, "*XAA", 2, 3, AddressMode::Immediate), //todo: highly unstable and not used
-        OpCode::new(0xbb, "*LAS", 3, 2, AddressMode::AbsoluteY), //todo: highly unstable and not used
-        OpCode::new(0x9b, "*TAS", 3, 2, AddressMode::AbsoluteY), //todo: highly unstable and not used
-        OpCode::new(0x93, "*AHX", 2, /* guess */ 8, AddressMode::IndirectY), //todo: highly unstable and not used
-        OpCode::new(0x9f, "*AHX", 3, /* guess */ 4/* or 5*/, AddressMode::AbsoluteY), //todo: highly unstable and not used
-        OpCode::new(0x9e, "*SHX", 3, /* guess */ 4/* or 5*/, AddressMode::AbsoluteY), //todo: highly unstable and not used
-        OpCode::new(0x9c, "*SHY", 3, /* guess */ 4/* or 5*/, AddressMode::AbsoluteX), //todo: highly unstable and not used
+        // unstable: the results depend on analog effects, these follow the common models
+        // http://visual6502.org/wiki/index.php?title=6502_Opcode_8B_%28XAA,_ANE%29
+        OpCode::new(0xab, "*LXA", 2, 2, AddressMode::Immediate),
+        OpCode::new(0x8b, "*XAA", 2, 2, AddressMode::Immediate),
+        OpCode::new(0xbb, "*LAS", 3, 4/*+1 if page crossed*/, AddressMode::AbsoluteY),
+        OpCode::new(0x9b, "*TAS", 3, 5, AddressMode::AbsoluteY),
+        OpCode::new(0x93, "*AHX", 2, 6, AddressMode::IndirectY),
+        OpCode::new(0x9f, "*AHX", 3, 5, AddressMode::AbsoluteY),
+        OpCode::new(0x9e, "*SHX", 3, 5, AddressMode::AbsoluteY),
+        OpCode::new(0x9c, "*SHY", 3, 5, AddressMode::AbsoluteX),
 
         OpCode::new(0xa7, "*LAX", 2, 3, AddressMode::ZeroPage),
         OpCode::new(0xb7, "*LAX", 2, 4, AddressMode::ZeroPageY),
         OpCode::new(0xaf, "*LAX", 3, 4, AddressMode::Absolute),
-        OpCode::new(0xbf, "*LAX", 3, 4, AddressMode::AbsoluteY),
+        OpCode::new(0xbf, "*LAX", 3, 4/*+1 if page crossed*/, AddressMode::AbsoluteY),
         OpCode::new(0xa3, "*LAX", 2, 6, AddressMode::IndirectX),
-        OpCode::new(0xb3, "*LAX", 2, 5, AddressMode::IndirectY),
+        OpCode::new(0xb3, "*LAX", 2, 5/*+1 if page crossed*/, AddressMode::IndirectY),
 
         OpCode::new(0x87, "*SAX", 2, 3, AddressMode::ZeroPage),
         OpCode::new(0x97, "*SAX", 2, 4, AddressMode::ZeroPageY),
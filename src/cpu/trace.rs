@@ -0,0 +1,146 @@
+use crate::cpu::CPU;
+use crate::disasm::{self, Operand};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+/// Formats the instruction at PC and the CPU state before it runs, in the layout
+/// of the nestest golden log:
+///
+/// ```text
+/// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+/// ```
+///
+/// Memory is only peeked, so tracing never changes what the program observes.
+pub fn trace(cpu: &CPU) -> String {
+    let bus = &cpu.bus;
+    let begin = cpu.pc;
+    let instruction = disasm::decode(|addr| bus.peek(addr), begin);
+    let read_word_zp = |ptr: u8| {
+        let lo = bus.peek(ptr as u16) as u16;
+        let hi = bus.peek(ptr.wrapping_add(1) as u16) as u16;
+        hi << 8 | lo
+    };
+
+    let operand = match instruction.operand {
+        Operand::Implied => String::new(),
+        Operand::Accumulator => "A".to_string(),
+        Operand::Immediate(value) => format!("#${:02X}", value),
+        Operand::ZeroPage(zp) => format!("${:02X} = {:02X}", zp, bus.peek(zp as u16)),
+        Operand::ZeroPageX(zp) => {
+            let addr = zp.wrapping_add(cpu.x) as u16;
+            format!("${:02X},X @ {:02X} = {:02X}", zp, addr, bus.peek(addr))
+        }
+        Operand::ZeroPageY(zp) => {
+            let addr = zp.wrapping_add(cpu.y) as u16;
+            format!("${:02X},Y @ {:02X} = {:02X}", zp, addr, bus.peek(addr))
+        }
+        Operand::Absolute(abs) if instruction.target().is_some() => format!("${:04X}", abs),
+        Operand::Absolute(abs) => format!("${:04X} = {:02X}", abs, bus.peek(abs)),
+        Operand::AbsoluteX(abs) => {
+            let addr = abs.wrapping_add(cpu.x as u16);
+            format!("${:04X},X @ {:04X} = {:02X}", abs, addr, bus.peek(addr))
+        }
+        Operand::AbsoluteY(abs) => {
+            let addr = abs.wrapping_add(cpu.y as u16);
+            format!("${:04X},Y @ {:04X} = {:02X}", abs, addr, bus.peek(addr))
+        }
+        Operand::Indirect(ptr) => {
+            let lo = bus.peek(ptr) as u16;
+            let hi = bus.peek((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF)) as u16;
+            format!("(${:04X}) = {:04X}", ptr, hi << 8 | lo)
+        }
+        Operand::IndirectX(zp) => {
+            let ptr = zp.wrapping_add(cpu.x);
+            let addr = read_word_zp(ptr);
+            format!(
+                "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                zp,
+                ptr,
+                addr,
+                bus.peek(addr)
+            )
+        }
+        Operand::IndirectY(zp) => {
+            let base = read_word_zp(zp);
+            let addr = base.wrapping_add(cpu.y as u16);
+            format!(
+                "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                zp,
+                base,
+                addr,
+                bus.peek(addr)
+            )
+        }
+        Operand::Relative(target) => format!("${:04X}", target),
+    };
+
+    let hex_str = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let asm_str = format!(
+        "{:04X}  {:8} {: >4} {}",
+        begin, hex_str, instruction.mnemonic, operand
+    );
+
+    format!(
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        asm_str.trim_end(),
+        cpu.a,
+        cpu.x,
+        cpu.y,
+        cpu.status.bits(),
+        cpu.sp,
+        bus.ppu.scanline(),
+        bus.ppu.dot(),
+        bus.cycles()
+    )
+}
+
+/// Writes a `trace` line for every instruction the CPU executes, see `CPU::set_tracer`.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    enabled: bool,
+    pc_range: RangeInclusive<u16>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Self {
+        Tracer {
+            out: Box::new(out),
+            enabled: true,
+            pc_range: 0x0000..=0xFFFF,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Only instructions starting inside `range` are logged.
+    pub fn set_pc_range(&mut self, range: RangeInclusive<u16>) {
+        self.pc_range = range;
+    }
+
+    pub fn flush(&mut self) {
+        // a failing trace writer must not take the emulation down with it
+        if self.out.flush().is_err() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn log(&mut self, cpu: &CPU) {
+        if !self.enabled || !self.pc_range.contains(&cpu.pc) {
+            return;
+        }
+        if writeln!(self.out, "{}", trace(cpu)).is_err() {
+            self.enabled = false;
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Breakpoints, watchpoints and stepping on top of `CPU::step`.
+//!
+//! The debugger owns no emulator state: every command takes the CPU, runs it until
+//! something interesting happens and reports why it stopped. `repl` wraps the same
+//! API in a line-based terminal interface.
+
+pub mod repl;
+
+use crate::cpu::{InterruptType, CPU};
+use bitflags::bitflags;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+const SCANLINES_PER_FRAME: usize = 262;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    Ppu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// Opcode and operand fetches.
+    Execute,
+}
+
+/// A single bus access, as recorded by `CPU::record_accesses`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub space: AddressSpace,
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub data: u8,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Execute => "execute",
+        };
+        let space = match self.space {
+            AddressSpace::Cpu => "",
+            AddressSpace::Ppu => "PPU ",
+        };
+        write!(
+            f,
+            "{} {}${:04X} = {:02X}",
+            kind, space, self.addr, self.data
+        )
+    }
+}
+
+bitflags! {
+    /// Which kinds of access trigger a watchpoint.
+    pub struct AccessKinds: u8 {
+        const READ    = 0b001;
+        const WRITE   = 0b010;
+        const EXECUTE = 0b100;
+    }
+}
+
+impl AccessKinds {
+    fn matches(&self, kind: AccessKind) -> bool {
+        match kind {
+            AccessKind::Read => self.contains(AccessKinds::READ),
+            AccessKind::Write => self.contains(AccessKinds::WRITE),
+            AccessKind::Execute => self.contains(AccessKinds::EXECUTE),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    P,
+    PC,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// `A == $10` style test on a register, checked when a breakpoint address is hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        let register = match self.register {
+            Register::A => cpu.a as u16,
+            Register::X => cpu.x as u16,
+            Register::Y => cpu.y as u16,
+            Register::SP => cpu.sp as u16,
+            Register::P => cpu.status.bits() as u16,
+            Register::PC => cpu.pc,
+        };
+        match self.comparison {
+            Comparison::Eq => register == self.value,
+            Comparison::Ne => register != self.value,
+            Comparison::Lt => register < self.value,
+            Comparison::Le => register <= self.value,
+            Comparison::Gt => register > self.value,
+            Comparison::Ge => register >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comparison = match self.comparison {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, "{:?} {} ${:X}", self.register, comparison, self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: u16,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub space: AddressSpace,
+    pub range: RangeInclusive<u16>,
+    pub kinds: AccessKinds,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> bool {
+        self.space == access.space
+            && self.kinds.matches(access.kind)
+            && self.range.contains(&access.addr)
+    }
+}
+
+/// Why a debugger command returned control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The step, step over or step out finished.
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint {
+        id: usize,
+        access: Access,
+    },
+    Scanline(u16),
+    Nmi,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Stepped => write!(f, "stepped"),
+            StopReason::Breakpoint(id) => write!(f, "breakpoint #{}", id),
+            StopReason::Watchpoint { id, access } => write!(f, "watchpoint #{}: {}", id, access),
+            StopReason::Scanline(scanline) => write!(f, "reached scanline {}", scanline),
+            StopReason::Nmi => write!(f, "entered NMI handler"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Step,
+    Return { pc: u16, sp: u8 },
+    Out { sp: u8 },
+    Continue,
+    Scanline(u16),
+    Nmi,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    /// Stops before the instruction at `addr` runs, if `condition` holds then.
+    /// Returns the id used by `remove`.
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<Condition>) -> usize {
+        let id = self.allocate_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            addr,
+            condition,
+        });
+        id
+    }
+
+    /// Stops after the instruction that made a matching access. PPU space is only
+    /// watched through PPUDATA, not the rendering fetches.
+    pub fn add_watchpoint(
+        &mut self,
+        space: AddressSpace,
+        range: RangeInclusive<u16>,
+        kinds: AccessKinds,
+    ) -> usize {
+        let id = self.allocate_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            space,
+            range,
+            kinds,
+        });
+        id
+    }
+
+    /// Removes the breakpoint or watchpoint with `id`, returning whether it existed.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        before != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Runs one instruction, or enters the handler of a pending interrupt.
+    pub fn step_into(&mut self, cpu: &mut CPU) -> StopReason {
+        self.run_until(cpu, Target::Step)
+    }
+
+    /// Like `step_into`, but runs a `JSR` until the subroutine returns.
+    pub fn step_over(&mut self, cpu: &mut CPU) -> StopReason {
+        if cpu.bus.peek(cpu.pc) == JSR {
+            let target = Target::Return {
+                pc: cpu.pc.wrapping_add(3),
+                sp: cpu.sp,
+            };
+            self.run_until(cpu, target)
+        } else {
+            self.run_until(cpu, Target::Step)
+        }
+    }
+
+    /// Runs until the current subroutine or interrupt handler returns.
+    pub fn step_out(&mut self, cpu: &mut CPU) -> StopReason {
+        self.run_until(cpu, Target::Out { sp: cpu.sp })
+    }
+
+    /// Runs until a breakpoint or watchpoint triggers.
+    pub fn run(&mut self, cpu: &mut CPU) -> StopReason {
+        self.run_until(cpu, Target::Continue)
+    }
+
+    /// Runs until the PPU starts `scanline` (0-261).
+    pub fn run_to_scanline(&mut self, cpu: &mut CPU, scanline: u16) -> StopReason {
+        self.run_until(cpu, Target::Scanline(scanline))
+    }
+
+    /// Runs until an NMI is taken, stopping on the first instruction of its handler.
+    pub fn run_to_nmi(&mut self, cpu: &mut CPU) -> StopReason {
+        self.run_until(cpu, Target::Nmi)
+    }
+
+    fn allocate_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn run_until(&mut self, cpu: &mut CPU, target: Target) -> StopReason {
+        cpu.record_accesses(!self.watchpoints.is_empty());
+        let stop = self.run_to(cpu, target);
+        // the log would grow without bound once the CPU runs outside the debugger
+        cpu.record_accesses(false);
+        stop
+    }
+
+    fn run_to(&mut self, cpu: &mut CPU, target: Target) -> StopReason {
+        // the breakpoint we are sitting on must not stop us again
+        let mut resuming = true;
+        loop {
+            if let Some(interrupt) = cpu.service_interrupts() {
+                if let Some(stop) = self.watchpoint_hit(cpu) {
+                    return stop;
+                }
+                if interrupt == InterruptType::NMI && target == Target::Nmi {
+                    return StopReason::Nmi;
+                }
+                if target == Target::Step {
+                    return StopReason::Stepped;
+                }
+                resuming = false;
+            }
+
+            if !resuming {
+                if let Some(id) = self.breakpoint_hit(cpu) {
+                    return StopReason::Breakpoint(id);
+                }
+            }
+            resuming = false;
+
+            let opcode = cpu.bus.peek(cpu.pc);
+            let line_before = absolute_scanline(cpu);
+            cpu.step();
+
+            if let Some(stop) = self.watchpoint_hit(cpu) {
+                return stop;
+            }
+            match target {
+                Target::Step => return StopReason::Stepped,
+                Target::Return { pc, sp } if cpu.pc == pc && cpu.sp == sp => {
+                    return StopReason::Stepped
+                }
+                Target::Out { sp } if (opcode == RTS || opcode == RTI) && cpu.sp > sp => {
+                    return StopReason::Stepped
+                }
+                Target::Scanline(scanline) => {
+                    let passed = (line_before + 1..=absolute_scanline(cpu))
+                        .any(|line| line % SCANLINES_PER_FRAME == scanline as usize);
+                    if passed {
+                        return StopReason::Scanline(scanline);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn breakpoint_hit(&self, cpu: &CPU) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| {
+                breakpoint.addr == cpu.pc
+                    && breakpoint
+                        .condition
+                        .map_or(true, |condition| condition.holds(cpu))
+            })
+            .map(|breakpoint| breakpoint.id)
+    }
+
+    fn watchpoint_hit(&self, cpu: &mut CPU) -> Option<StopReason> {
+        let watchpoints = &self.watchpoints;
+        cpu.drain_accesses()
+            .find_map(|access| {
+                watchpoints
+                    .iter()
+                    .find(|watchpoint| watchpoint.matches(&access))
+                    .map(|watchpoint| (watchpoint.id, access))
+            })
+            .map(|(id, access)| StopReason::Watchpoint { id, access })
+    }
+}
+
+fn absolute_scanline(cpu: &CPU) -> usize {
+    cpu.bus.ppu.frame_count() * SCANLINES_PER_FRAME + cpu.bus.ppu.scanline() as usize
+}
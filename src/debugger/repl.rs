@@ -0,0 +1,321 @@
+//! A line-based front end for `Debugger`. Addresses and values are hex, counts and
+//! scanlines are decimal. An empty line repeats the previous command.
+
+use crate::cpu::trace::trace;
+use crate::cpu::CPU;
+use crate::debugger::{
+    AccessKinds, AddressSpace, Comparison, Condition, Debugger, Register, StopReason,
+};
+use crate::disasm;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+
+const PROMPT: &str = "(nes) ";
+const DEFAULT_DISASM_COUNT: usize = 10;
+const DEFAULT_DUMP_LEN: u16 = 64;
+
+const HELP: &str = "\
+break ADDR [if REG OP VALUE]     stop before ADDR runs, REG is A X Y SP P or PC
+watch [r|w|x|rw|...] [ppu] ADDR[-END]
+                                 stop after a matching access (default rw)
+delete ID                        remove a breakpoint or watchpoint
+list                             show breakpoints and watchpoints
+step [N] | next | out            step into, over or out of a subroutine
+continue                         run to the next breakpoint or watchpoint
+scanline N | nmi                 run to the start of a scanline or the NMI handler
+regs                             show the current instruction and registers
+mem [ppu] ADDR [LEN]             dump memory
+dis [ADDR] [N]                   disassemble N instructions
+quit";
+
+/// Reads commands from `input` until it ends or `quit` is entered.
+pub fn run<R: BufRead, W: Write>(
+    debugger: &mut Debugger,
+    cpu: &mut CPU,
+    input: R,
+    mut out: W,
+) -> io::Result<()> {
+    writeln!(out, "{}", trace(cpu))?;
+    write!(out, "{}", PROMPT)?;
+    out.flush()?;
+
+    let mut last = String::new();
+    for line in input.lines() {
+        let line = line?;
+        let line = if line.trim().is_empty() {
+            last.clone()
+        } else {
+            line.trim().to_string()
+        };
+
+        if !line.is_empty() {
+            match execute(debugger, cpu, &line, &mut out) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(Error::Usage(message)) => writeln!(out, "error: {}", message)?,
+                Err(Error::Io(err)) => return Err(err),
+            }
+        }
+        last = line;
+
+        write!(out, "{}", PROMPT)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+enum Error {
+    Usage(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Usage(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Usage(message.to_string())
+    }
+}
+
+/// Returns whether the session should end.
+fn execute<W: Write>(
+    debugger: &mut Debugger,
+    cpu: &mut CPU,
+    line: &str,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let mut args = line.split_whitespace();
+    let command = args.next().unwrap_or_default();
+    let args: Vec<&str> = args.collect();
+
+    let stop = match command {
+        "b" | "break" => {
+            let addr = parse_addr(args.first().ok_or("break needs an address")?)?;
+            let condition = match args.get(1) {
+                Some(&"if") => Some(parse_condition(&args[2..].concat())?),
+                Some(arg) => return Err(format!("unexpected argument '{}'", arg).into()),
+                None => None,
+            };
+            let id = debugger.add_breakpoint(addr, condition);
+            writeln!(out, "breakpoint #{} at ${:04X}", id, addr)?;
+            None
+        }
+        "w" | "watch" => {
+            let (space, range, kinds) = parse_watch(&args)?;
+            let id = debugger.add_watchpoint(space, range, kinds);
+            writeln!(out, "watchpoint #{}", id)?;
+            None
+        }
+        "d" | "delete" => {
+            let id = args.first().ok_or("delete needs an id")?;
+            let id = id
+                .trim_start_matches('#')
+                .parse()
+                .map_err(|_| format!("invalid id '{}'", id))?;
+            if !debugger.remove(id) {
+                return Err(format!("no breakpoint or watchpoint #{}", id).into());
+            }
+            None
+        }
+        "l" | "list" => {
+            for breakpoint in debugger.breakpoints() {
+                write!(out, "#{}  break ${:04X}", breakpoint.id, breakpoint.addr)?;
+                if let Some(condition) = breakpoint.condition {
+                    write!(out, " if {}", condition)?;
+                }
+                writeln!(out)?;
+            }
+            for watchpoint in debugger.watchpoints() {
+                writeln!(
+                    out,
+                    "#{}  watch {:?} {:?} ${:04X}-${:04X}",
+                    watchpoint.id,
+                    watchpoint.kinds,
+                    watchpoint.space,
+                    watchpoint.range.start(),
+                    watchpoint.range.end()
+                )?;
+            }
+            None
+        }
+        "s" | "step" => {
+            let count = match args.first() {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| format!("invalid count '{}'", count))?,
+                None => 1,
+            };
+            let mut stop = StopReason::Stepped;
+            for _ in 0..count {
+                stop = debugger.step_into(cpu);
+                if stop != StopReason::Stepped {
+                    break;
+                }
+            }
+            Some(stop)
+        }
+        "n" | "next" => Some(debugger.step_over(cpu)),
+        "o" | "out" | "finish" => Some(debugger.step_out(cpu)),
+        "c" | "continue" => Some(debugger.run(cpu)),
+        "scanline" => {
+            let arg = args.first().ok_or("scanline needs a number")?;
+            let scanline = arg
+                .parse()
+                .ok()
+                .filter(|scanline| *scanline < 262)
+                .ok_or_else(|| format!("invalid scanline '{}'", arg))?;
+            Some(debugger.run_to_scanline(cpu, scanline))
+        }
+        "nmi" => Some(debugger.run_to_nmi(cpu)),
+        "r" | "regs" => {
+            writeln!(out, "{}", trace(cpu))?;
+            None
+        }
+        "m" | "mem" => {
+            let (space, args) = match args.first() {
+                Some(&"ppu") => (AddressSpace::Ppu, &args[1..]),
+                _ => (AddressSpace::Cpu, &args[..]),
+            };
+            let addr = parse_addr(args.first().ok_or("mem needs an address")?)?;
+            let len = match args.get(1) {
+                Some(len) => len
+                    .parse()
+                    .map_err(|_| format!("invalid length '{}'", len))?,
+                None => DEFAULT_DUMP_LEN,
+            };
+            dump(cpu, space, addr, len, out)?;
+            None
+        }
+        "dis" => {
+            let mut addr = match args.first() {
+                Some(addr) => parse_addr(addr)?,
+                None => cpu.pc,
+            };
+            let count = match args.get(1) {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| format!("invalid count '{}'", count))?,
+                None => DEFAULT_DISASM_COUNT,
+            };
+            for _ in 0..count {
+                let instruction = disasm::decode(|addr| cpu.bus.peek(addr), addr);
+                let marker = if addr == cpu.pc { "=>" } else { "  " };
+                writeln!(out, "{} {}", marker, instruction)?;
+                addr = instruction.next_addr();
+            }
+            None
+        }
+        "h" | "help" => {
+            writeln!(out, "{}", HELP)?;
+            None
+        }
+        "q" | "quit" => return Ok(true),
+        _ => return Err(format!("unknown command '{}', try 'help'", command).into()),
+    };
+
+    if let Some(stop) = stop {
+        writeln!(out, "{}", stop)?;
+        writeln!(out, "{}", trace(cpu))?;
+    }
+    Ok(false)
+}
+
+fn dump<W: Write>(
+    cpu: &CPU,
+    space: AddressSpace,
+    addr: u16,
+    len: u16,
+    out: &mut W,
+) -> io::Result<()> {
+    let peek = |addr: u16| match space {
+        AddressSpace::Cpu => cpu.bus.peek(addr),
+        AddressSpace::Ppu => cpu.bus.ppu.peek(addr),
+    };
+    for row in (0..len).step_by(16) {
+        let start = addr.wrapping_add(row);
+        let bytes: Vec<String> = (0..16.min(len - row))
+            .map(|i| format!("{:02X}", peek(start.wrapping_add(i))))
+            .collect();
+        writeln!(out, "{:04X}  {}", start, bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+fn parse_addr(value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", value))
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let split = condition
+        .find(|c: char| "=!<>".contains(c))
+        .ok_or_else(|| format!("invalid condition '{}'", condition))?;
+    let (register, rest) = condition.split_at(split);
+    let register = match register.to_ascii_uppercase().as_str() {
+        "A" => Register::A,
+        "X" => Register::X,
+        "Y" => Register::Y,
+        "SP" => Register::SP,
+        "P" => Register::P,
+        "PC" => Register::PC,
+        _ => return Err(format!("unknown register '{}'", register)),
+    };
+
+    let (comparison, value) = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+        ("=", Comparison::Eq),
+    ]
+    .iter()
+    .find_map(|(op, comparison)| rest.strip_prefix(op).map(|value| (*comparison, value)))
+    .ok_or_else(|| format!("invalid condition '{}'", condition))?;
+
+    Ok(Condition {
+        register,
+        comparison,
+        value: parse_addr(value)?,
+    })
+}
+
+fn parse_watch(args: &[&str]) -> Result<(AddressSpace, RangeInclusive<u16>, AccessKinds), String> {
+    let mut kinds = AccessKinds::READ | AccessKinds::WRITE;
+    let mut space = AddressSpace::Cpu;
+    let mut range = None;
+
+    for arg in args {
+        match *arg {
+            "ppu" => space = AddressSpace::Ppu,
+            _ if arg.chars().all(|c| "rwx".contains(c)) => {
+                kinds = AccessKinds::empty();
+                for c in arg.chars() {
+                    kinds |= match c {
+                        'r' => AccessKinds::READ,
+                        'w' => AccessKinds::WRITE,
+                        _ => AccessKinds::EXECUTE,
+                    };
+                }
+            }
+            _ => {
+                let (start, end) = arg.split_once('-').unwrap_or((arg, arg));
+                range = Some(parse_addr(start)?..=parse_addr(end)?);
+            }
+        }
+    }
+
+    let range = range.ok_or("watch needs an address or range")?;
+    Ok((space, range, kinds))
+}
@@ -0,0 +1,249 @@
+//! Turns 6502 machine code into assembly text using the opcode table.
+//!
+//! Bytes are fetched through `Bus::peek` (or any other read function), so
+//! disassembling a range that covers PPU or controller registers never changes
+//! what the running program observes.
+
+use crate::bus::Bus;
+use crate::cpu::instructions::AddressMode;
+use crate::cpu::opcodes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The decoded operand of an instruction. Branches keep their resolved target
+/// instead of the raw offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Implied,
+    Accumulator,
+    Immediate(u8),
+    ZeroPage(u8),
+    ZeroPageX(u8),
+    ZeroPageY(u8),
+    Absolute(u16),
+    AbsoluteX(u16),
+    AbsoluteY(u16),
+    Indirect(u16),
+    IndirectX(u8),
+    IndirectY(u8),
+    Relative(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operand: Operand,
+}
+
+impl Instruction {
+    /// Address of the instruction that follows this one in memory.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Where a taken branch, `JMP` or `JSR` continues. Indirect jumps have no
+    /// static target.
+    pub fn target(&self) -> Option<u16> {
+        match (self.mnemonic, self.operand) {
+            (_, Operand::Relative(target)) => Some(target),
+            ("JMP" | "JSR", Operand::Absolute(target)) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Formats the operand, naming the addresses `symbol` knows about.
+    pub fn operand_text<'a>(&self, symbol: impl Fn(u16) -> Option<&'a str>) -> String {
+        let addr = |addr: u16| match symbol(addr) {
+            Some(name) => name.to_string(),
+            None => format!("${:04X}", addr),
+        };
+        match self.operand {
+            Operand::Implied => String::new(),
+            Operand::Accumulator => "A".to_string(),
+            Operand::Immediate(value) => format!("#${:02X}", value),
+            Operand::ZeroPage(zp) => format!("${:02X}", zp),
+            Operand::ZeroPageX(zp) => format!("${:02X},X", zp),
+            Operand::ZeroPageY(zp) => format!("${:02X},Y", zp),
+            Operand::Absolute(abs) | Operand::Relative(abs) => addr(abs),
+            Operand::AbsoluteX(abs) => format!("{},X", addr(abs)),
+            Operand::AbsoluteY(abs) => format!("{},Y", addr(abs)),
+            Operand::Indirect(ptr) => format!("({})", addr(ptr)),
+            Operand::IndirectX(zp) => format!("(${:02X},X)", zp),
+            Operand::IndirectY(zp) => format!("(${:02X}),Y", zp),
+        }
+    }
+
+    fn write_line(&self, f: &mut fmt::Formatter, operand: &str) -> fmt::Result {
+        let hex = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let line = format!(
+            "{:04X}  {:8}  {} {}",
+            self.addr, hex, self.mnemonic, operand
+        );
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+/// `C000  AD 02 20  LDA PPUSTATUS`, with hardware registers named.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_line(f, &self.operand_text(register_name))
+    }
+}
+
+/// A disassembled range with `L_xxxx` labels on every branch, `JMP` and `JSR`
+/// target that starts one of its instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub instructions: Vec<Instruction>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+impl Listing {
+    /// The label or hardware register name for `addr`.
+    pub fn symbol(&self, addr: u16) -> Option<&str> {
+        self.labels
+            .get(&addr)
+            .map(String::as_str)
+            .or_else(|| register_name(addr))
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.instructions {
+            if let Some(label) = self.labels.get(&instruction.addr) {
+                writeln!(f, "{}:", label)?;
+            }
+            instruction.write_line(f, &instruction.operand_text(|addr| self.symbol(addr)))?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The conventional name of a PPU, APU or I/O register.
+pub fn register_name(addr: u16) -> Option<&'static str> {
+    let name = match addr {
+        0x2000 => "PPUCTRL",
+        0x2001 => "PPUMASK",
+        0x2002 => "PPUSTATUS",
+        0x2003 => "OAMADDR",
+        0x2004 => "OAMDATA",
+        0x2005 => "PPUSCROLL",
+        0x2006 => "PPUADDR",
+        0x2007 => "PPUDATA",
+        0x4000 => "SQ1_VOL",
+        0x4001 => "SQ1_SWEEP",
+        0x4002 => "SQ1_LO",
+        0x4003 => "SQ1_HI",
+        0x4004 => "SQ2_VOL",
+        0x4005 => "SQ2_SWEEP",
+        0x4006 => "SQ2_LO",
+        0x4007 => "SQ2_HI",
+        0x4008 => "TRI_LINEAR",
+        0x400A => "TRI_LO",
+        0x400B => "TRI_HI",
+        0x400C => "NOISE_VOL",
+        0x400E => "NOISE_LO",
+        0x400F => "NOISE_HI",
+        0x4010 => "DMC_FREQ",
+        0x4011 => "DMC_RAW",
+        0x4012 => "DMC_START",
+        0x4013 => "DMC_LEN",
+        0x4014 => "OAMDMA",
+        0x4015 => "SND_CHN",
+        0x4016 => "JOY1",
+        0x4017 => "JOY2",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Decodes the instruction starting at `addr`. Every byte is a valid opcode, the
+/// unofficial ones carry a `*` in front of the mnemonic.
+pub fn decode(read: impl Fn(u16) -> u8, addr: u16) -> Instruction {
+    let code = read(addr);
+    let opcode = opcodes::OPCODES_MAP
+        .get(&code)
+        .expect("Opcode is not recognized");
+
+    let bytes: Vec<u8> = (0..opcode.len as u16)
+        .map(|i| read(addr.wrapping_add(i)))
+        .collect();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = (bytes.get(2).copied().unwrap_or(0) as u16) << 8 | byte as u16;
+
+    let operand = match opcode.mode {
+        AddressMode::Immediate => Operand::Immediate(byte),
+        AddressMode::ZeroPage => Operand::ZeroPage(byte),
+        AddressMode::ZeroPageX => Operand::ZeroPageX(byte),
+        AddressMode::ZeroPageY => Operand::ZeroPageY(byte),
+        AddressMode::Absolute => Operand::Absolute(word),
+        AddressMode::AbsoluteX => Operand::AbsoluteX(word),
+        AddressMode::AbsoluteY => Operand::AbsoluteY(word),
+        AddressMode::Indirect => Operand::Indirect(word),
+        AddressMode::IndirectX => Operand::IndirectX(byte),
+        AddressMode::IndirectY => Operand::IndirectY(byte),
+        // the table keeps JMP, JSR, branches and implied instructions under None
+        AddressMode::None => match (code, opcode.len) {
+            (0x0A | 0x4A | 0x2A | 0x6A, _) => Operand::Accumulator,
+            (0x6C, _) => Operand::Indirect(word),
+            (_, 3) => Operand::Absolute(word),
+            (_, 2) => {
+                let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+                Operand::Relative(target)
+            }
+            _ => Operand::Implied,
+        },
+    };
+
+    Instruction {
+        addr,
+        bytes,
+        mnemonic: opcode.mnemonic,
+        operand,
+    }
+}
+
+/// Decodes every instruction that starts inside `range`, reading through `read`.
+/// The last instruction may extend past the end of the range.
+pub fn disassemble_with(read: impl Fn(u16) -> u8, range: RangeInclusive<u16>) -> Listing {
+    let mut instructions = Vec::new();
+    let mut addr = *range.start();
+    while range.contains(&addr) {
+        let instruction = decode(&read, addr);
+        let next = instruction.next_addr();
+        instructions.push(instruction);
+        // stop instead of wrapping around to $0000
+        if next <= addr {
+            break;
+        }
+        addr = next;
+    }
+
+    let starts: Vec<u16> = instructions.iter().map(|ins| ins.addr).collect();
+    let labels = instructions
+        .iter()
+        .filter_map(Instruction::target)
+        .filter(|target| starts.binary_search(target).is_ok())
+        .map(|target| (target, format!("L_{:04X}", target)))
+        .collect();
+
+    Listing {
+        instructions,
+        labels,
+    }
+}
+
+/// Disassembles `range` as the CPU currently sees it, without side effects.
+pub fn disassemble(bus: &Bus, range: RangeInclusive<u16>) -> Listing {
+    disassemble_with(|addr| bus.peek(addr), range)
+}
@@ -0,0 +1,104 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use bitflags::bitflags;
+
+bitflags! {
+    pub struct JoypadButton: u8 {
+        const RIGHT    = 0b10000000;
+        const LEFT     = 0b01000000;
+        const DOWN     = 0b00100000;
+        const UP       = 0b00010000;
+        const START    = 0b00001000;
+        const SELECT   = 0b00000100;
+        const BUTTON_B = 0b00000010;
+        const BUTTON_A = 0b00000001;
+    }
+}
+
+/// A device plugged into one of the controller ports ($4016/$4017).
+pub trait InputDevice: Snapshot {
+    /// $4016 write. Bit 0 is the strobe line, shared by both ports.
+    fn write(&mut self, data: u8);
+
+    /// Serial data returned on $4016/$4017 reads, in the low 5 bits.
+    fn read(&mut self) -> u8;
+
+    /// What `read` would return, without shifting the register.
+    fn peek(&self) -> u8;
+
+    /// Feeds the current button state. Devices without buttons ignore it.
+    fn set_buttons(&mut self, _buttons: JoypadButton) {}
+}
+
+/// Standard NES controller: an 8-bit parallel-in/serial-out shift register.
+pub struct Joypad {
+    strobe: bool,
+    button_index: u8,
+    button_status: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            button_index: 0,
+            button_status: JoypadButton::from_bits_truncate(0),
+        }
+    }
+
+    pub fn set_button_pressed_status(&mut self, button: JoypadButton, pressed: bool) {
+        self.button_status.set(button, pressed);
+    }
+
+    pub fn buttons(&self) -> JoypadButton {
+        self.button_status
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+impl InputDevice for Joypad {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.button_index = 0;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let response = self.peek();
+        if !self.strobe && self.button_index <= 7 {
+            self.button_index += 1;
+        }
+        response
+    }
+
+    fn peek(&self) -> u8 {
+        // official controllers report 1 once all 8 buttons have been shifted out
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.button_status.bits & (1 << self.button_index)) >> self.button_index
+    }
+
+    fn set_buttons(&mut self, buttons: JoypadButton) {
+        self.button_status = buttons;
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save(&self, state: &mut StateWriter) {
+        state.bool(self.strobe);
+        state.u8(self.button_index);
+        state.u8(self.button_status.bits);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.strobe = state.bool();
+        self.button_index = state.u8();
+        self.button_status = JoypadButton::from_bits_truncate(state.u8());
+    }
+}
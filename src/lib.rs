@@ -1,5 +1,11 @@
+pub mod apu;
 pub mod bus;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod joypad;
+pub mod movie;
 pub mod ppu;
 pub mod render;
 pub mod rom;
+pub mod savestate;
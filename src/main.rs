@@ -1,60 +1,264 @@
+use nes::apu::AudioSink;
 use nes::bus::Bus;
 use nes::cpu::CPU;
+use nes::joypad::{InputDevice, JoypadButton};
+use nes::movie::{Movie, Player, Recorder};
 use nes::ppu::PPU;
 use nes::render;
 use nes::render::frame::Frame;
 use nes::rom::Rom;
+use nes::savestate::rewind::Rewind;
 
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+const SAMPLE_RATE: i32 = 44_100;
+const AUDIO_BUFFER_SIZE: usize = 1024;
+// ten seconds at 60 frames per second
+const REWIND_FRAMES: usize = 600;
+
+struct SdlAudio {
+    queue: AudioQueue<f32>,
+    buffer: Vec<f32>,
+}
+
+impl AudioSink for SdlAudio {
+    fn push_sample(&mut self, sample: f32) {
+        self.buffer.push(sample);
+        if self.buffer.len() >= AUDIO_BUFFER_SIZE {
+            self.queue.queue_audio(&self.buffer).unwrap();
+            self.buffer.clear();
+        }
+    }
+}
+
+enum MovieMode {
+    None,
+    Record(String),
+    Play(String),
+}
+
+fn usage() -> ! {
+    eprintln!("usage: nes [--debug-view] [--record FILE.fm2 | --play FILE.fm2]");
+    std::process::exit(2);
+}
+
+/// The movie mode, and whether to show the debug composite instead of the picture.
+fn parse_args() -> (MovieMode, bool) {
+    let mut movie_mode = MovieMode::None;
+    let mut debug_view = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--debug-view" => debug_view = true,
+            "--record" | "--play" if matches!(movie_mode, MovieMode::None) => {
+                let path = args.next().unwrap_or_else(|| usage());
+                movie_mode = if arg == "--record" {
+                    MovieMode::Record(path)
+                } else {
+                    MovieMode::Play(path)
+                };
+            }
+            _ => usage(),
+        }
+    }
+    (movie_mode, debug_view)
+}
 
 fn main() {
+    let (movie_mode, debug_view) = parse_args();
+    let mut frame = if debug_view {
+        Frame::with_size(render::DEBUG_WIDTH, render::DEBUG_HEIGHT)
+    } else {
+        Frame::new()
+    };
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("nesmulator", (256.0 * 4.0) as u32, (240.0 * 2.0) as u32)
+        .window(
+            "nesmulator",
+            (frame.width() * 3) as u32,
+            (frame.height() * 3) as u32,
+        )
         .position_centered()
         .build()
         .unwrap();
 
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let audio_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: Some(AUDIO_BUFFER_SIZE as u16),
+    };
+    let queue: AudioQueue<f32> = audio_subsystem.open_queue(None, &audio_spec).unwrap();
+    queue.resume();
+
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     canvas.set_scale(3.0, 3.0).unwrap();
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
+        .create_texture_target(
+            PixelFormatEnum::RGB24,
+            frame.width() as u32,
+            frame.height() as u32,
+        )
         .unwrap();
 
     //load the game
-    let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+    let rom_path = Path::new("src/samples/Balloon Fight (USA).nes");
+    let bytes: Vec<u8> = std::fs::read(rom_path).unwrap();
     let rom = Rom::new(&bytes).unwrap();
+    let save_path = rom_path.with_extension("sav");
 
-    let mut frame = Frame::new();
+    let mut key_map = HashMap::new();
+    key_map.insert(Keycode::Down, JoypadButton::DOWN);
+    key_map.insert(Keycode::Up, JoypadButton::UP);
+    key_map.insert(Keycode::Right, JoypadButton::RIGHT);
+    key_map.insert(Keycode::Left, JoypadButton::LEFT);
+    key_map.insert(Keycode::Space, JoypadButton::SELECT);
+    key_map.insert(Keycode::Return, JoypadButton::START);
+    key_map.insert(Keycode::A, JoypadButton::BUTTON_A);
+    key_map.insert(Keycode::S, JoypadButton::BUTTON_B);
+    let mut buttons = JoypadButton::empty();
+    let rewinding = Rc::new(Cell::new(false));
+    let rewind_held = rewinding.clone();
+    let quitting = Rc::new(Cell::new(false));
+    let quit_pressed = quitting.clone();
+    // movies feed the controllers at the start of each frame instead
+    let live_input = Rc::new(Cell::new(matches!(movie_mode, MovieMode::None)));
+    let live_input_enabled = live_input.clone();
+    let held_buttons = Rc::new(Cell::new(JoypadButton::empty()));
+    let held = held_buttons.clone();
 
     // run the game cycle
-    let bus = Bus::new(rom, move |ppu: &PPU| {
-        render::render(ppu, &mut frame);
-        texture.update(None, &frame.data, 256 * 2 * 3).unwrap();
+    let mut bus = Bus::new(
+        rom,
+        move |ppu: &PPU, joypads: &mut [Box<dyn InputDevice>; 2]| {
+            if debug_view {
+                render::render_debug(ppu, &mut frame);
+            } else {
+                render::render(ppu, &mut frame);
+            }
+            texture.update(None, &frame.data, frame.pitch()).unwrap();
+
+            canvas.copy(&texture, None, None).unwrap();
 
-        canvas.copy(&texture, None, None).unwrap();
+            canvas.present();
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => quit_pressed.set(true),
 
-        canvas.present();
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => std::process::exit(0),
-                _ => {}
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => rewind_held.set(true),
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => rewind_held.set(false),
+
+                    Event::KeyDown { keycode, .. } => {
+                        if let Some(button) = keycode.and_then(|key| key_map.get(&key)) {
+                            buttons.insert(*button);
+                        }
+                    }
+                    Event::KeyUp { keycode, .. } => {
+                        if let Some(button) = keycode.and_then(|key| key_map.get(&key)) {
+                            buttons.remove(*button);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            held.set(buttons);
+            if live_input_enabled.get() {
+                joypads[0].set_buttons(buttons);
             }
+        },
+    )
+    .unwrap();
+    // a movie has to start from the same cartridge RAM it was recorded with
+    if matches!(movie_mode, MovieMode::None) {
+        if let Ok(ram) = std::fs::read(&save_path) {
+            bus.mapper.borrow_mut().load_battery_ram(&ram);
         }
-    });
+    }
+    bus.apu.set_audio_sink(
+        Box::new(SdlAudio {
+            queue,
+            buffer: Vec::with_capacity(AUDIO_BUFFER_SIZE),
+        }),
+        SAMPLE_RATE as u32,
+    );
 
     let mut cpu = CPU::new(bus);
     cpu.reset();
-    // cpu.run();
-    cpu.run_with_callback(|cpu| {});
+
+    let movie = match &movie_mode {
+        MovieMode::Play(path) => {
+            let text = std::fs::read_to_string(path).unwrap();
+            Movie::from_fm2(&text).unwrap()
+        }
+        _ => Movie::default(),
+    };
+    let mut player = match movie_mode {
+        MovieMode::Play(_) => Some(Player::new(&movie, &mut cpu).unwrap()),
+        _ => None,
+    };
+    let rom_filename = rom_path.file_name().unwrap().to_string_lossy();
+    let mut recorder = match movie_mode {
+        MovieMode::Record(ref path) => {
+            Some((path.clone(), Recorder::power_on(&cpu, &rom_filename)))
+        }
+        _ => None,
+    };
+
+    // the gameloop callback runs in the middle of an instruction and only sees the
+    // PPU, so frames are captured here at the first instruction boundary of each
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut last_frame = cpu.bus.ppu.frame_count();
+    cpu.run_with_callback(move |cpu| {
+        if quitting.get() {
+            if let Some(ram) = cpu.bus.mapper.borrow().battery_ram() {
+                std::fs::write(&save_path, ram).unwrap();
+            }
+            if let Some((path, recorder)) = &recorder {
+                std::fs::write(path, recorder.movie().to_fm2()).unwrap();
+            }
+            std::process::exit(0)
+        }
+
+        if cpu.bus.ppu.frame_count() != last_frame {
+            if rewinding.get() {
+                rewind.step_back(cpu);
+            } else {
+                rewind.capture(cpu);
+            }
+            last_frame = cpu.bus.ppu.frame_count();
+        }
+
+        if let Some((_, recorder)) = &mut recorder {
+            recorder.update(cpu, [held_buttons.get(), JoypadButton::empty()]);
+        }
+        if let Some(playing) = &mut player {
+            if !playing.update(cpu) {
+                eprintln!("movie finished, switching to the keyboard");
+                live_input.set(true);
+                player = None;
+            }
+        }
+    });
 }
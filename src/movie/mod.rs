@@ -0,0 +1,263 @@
+//! Controller input recorded frame by frame, stored in FCEUX's FM2 text format.
+//!
+//! The core has no source of randomness, so a movie replayed from the same start
+//! reproduces the recorded run exactly. `Recorder` and `Player` are driven between
+//! instructions, from `CPU::run_with_callback` or a stepping loop, and apply a
+//! frame's buttons as soon as the PPU starts that frame. Both ports are standard
+//! controllers; Four Score, Zapper and reset or power commands are not supported.
+//!
+//! FCEUX latches input at a slightly different point of the frame, so its movies
+//! load fine but only stay in sync with games that read the controllers once per
+//! frame.
+
+use crate::cpu::CPU;
+use crate::joypad::JoypadButton;
+
+/// FM2 button columns, from the most significant bit of `JoypadButton` down.
+const BUTTON_CHARS: &[u8; 8] = b"RLDUTSBA";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Movie {
+    /// Save state to load before the first frame, `None` to start from power-on.
+    pub start: Option<Vec<u8>>,
+    /// Buttons held on both ports, one entry per frame.
+    pub frames: Vec<[JoypadButton; 2]>,
+    pub rom_filename: String,
+    /// Kept from an imported movie and written back unchanged.
+    pub rom_checksum: Option<String>,
+    pub rerecords: u32,
+}
+
+impl Movie {
+    /// Parses an FM2 movie.
+    pub fn from_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::default();
+        let mut ports = [1, 1];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                let frame = parse_input(line, ports)
+                    .map_err(|err| format!("line {}: {}", number + 1, err))?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("line {}: invalid {} '{}'", number + 1, key, value))
+            };
+            match key {
+                "version" if number()? != 3 => {
+                    return Err(format!("unsupported FM2 version {}", value));
+                }
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = Some(value.to_string()),
+                "rerecordCount" => movie.rerecords = number()?,
+                "fourscore" if number()? != 0 => {
+                    return Err("Four Score movies are not supported".to_string());
+                }
+                "port0" => ports[0] = number()?,
+                "port1" => ports[1] = number()?,
+                "savestate" => movie.start = Some(parse_blob(value)?),
+                _ => {}
+            }
+        }
+        if ports.iter().any(|port| *port > 1) {
+            return Err("only standard controllers are supported".to_string());
+        }
+        Ok(movie)
+    }
+
+    /// Formats the movie as FM2, with both ports as standard controllers.
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        text.push_str("version 3\n");
+        text.push_str("emuVersion 0\n");
+        text.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        text.push_str("palFlag 0\n");
+        text.push_str(&format!("romFilename {}\n", self.rom_filename));
+        if let Some(checksum) = &self.rom_checksum {
+            text.push_str(&format!("romChecksum {}\n", checksum));
+        }
+        text.push_str("guid 00000000-0000-0000-0000-000000000000\n");
+        text.push_str("fourscore 0\n");
+        text.push_str("port0 1\n");
+        text.push_str("port1 1\n");
+        text.push_str("port2 0\n");
+        if let Some(state) = &self.start {
+            let hex: String = state.iter().map(|byte| format!("{:02x}", byte)).collect();
+            text.push_str(&format!("savestate 0x{}\n", hex));
+        }
+        for [port0, port1] in &self.frames {
+            text.push_str(&format!(
+                "|0|{}|{}||\n",
+                format_buttons(*port0),
+                format_buttons(*port1)
+            ));
+        }
+        text
+    }
+}
+
+fn parse_input(line: &str, ports: [u32; 2]) -> Result<[JoypadButton; 2], String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 4 {
+        return Err(format!("malformed input line '{}'", line));
+    }
+    if fields[1].trim().parse::<u8>().unwrap_or(1) != 0 {
+        return Err("reset and power commands are not supported".to_string());
+    }
+
+    let mut frame = [JoypadButton::empty(); 2];
+    for (port, buttons) in frame.iter_mut().enumerate() {
+        if ports[port] == 0 {
+            continue;
+        }
+        let field = fields[port + 2].as_bytes();
+        if field.len() != BUTTON_CHARS.len() {
+            return Err(format!("expected 8 buttons, got '{}'", fields[port + 2]));
+        }
+        for (i, c) in field.iter().enumerate() {
+            if *c != b'.' && *c != b' ' {
+                *buttons |= JoypadButton::from_bits_truncate(0x80 >> i);
+            }
+        }
+    }
+    Ok(frame)
+}
+
+fn format_buttons(buttons: JoypadButton) -> String {
+    BUTTON_CHARS
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            if buttons.bits() & (0x80 >> i) != 0 {
+                *c as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+/// Decodes an FM2 `0x` hex blob. FCEUX's base64 blobs hold its own save states,
+/// which could not be loaded anyway.
+fn parse_blob(value: &str) -> Result<Vec<u8>, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .ok_or("only save states written by this emulator are supported")?;
+    if hex.len() % 2 != 0 {
+        return Err("odd number of hex digits in save state".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| "invalid hex digit in save state".to_string())
+        })
+        .collect()
+}
+
+/// Records the buttons applied at the start of every frame into a `Movie`.
+pub struct Recorder {
+    movie: Movie,
+    start_frame: usize,
+    last_frame: Option<usize>,
+}
+
+impl Recorder {
+    /// Starts a movie from power-on. `cpu` must have just been reset.
+    pub fn power_on(cpu: &CPU, rom_filename: &str) -> Self {
+        Recorder::new(cpu, None, rom_filename)
+    }
+
+    /// Starts a movie from the machine's current state.
+    pub fn from_state(cpu: &CPU, rom_filename: &str) -> Self {
+        Recorder::new(cpu, Some(cpu.save_state()), rom_filename)
+    }
+
+    fn new(cpu: &CPU, start: Option<Vec<u8>>, rom_filename: &str) -> Self {
+        Recorder {
+            movie: Movie {
+                start,
+                rom_filename: rom_filename.to_string(),
+                ..Movie::default()
+            },
+            start_frame: cpu.bus.ppu.frame_count(),
+            last_frame: None,
+        }
+    }
+
+    /// Call between instructions. When a new frame has started, `buttons` are fed
+    /// to the controllers and recorded for it. If the machine was rewound, the
+    /// frames after it are dropped and counted as a rerecord.
+    pub fn update(&mut self, cpu: &mut CPU, buttons: [JoypadButton; 2]) {
+        let frame = cpu.bus.ppu.frame_count();
+        if self.last_frame == Some(frame) {
+            return;
+        }
+        self.last_frame = Some(frame);
+
+        let index = frame.saturating_sub(self.start_frame);
+        if index < self.movie.frames.len() {
+            self.movie.frames.truncate(index);
+            self.movie.rerecords += 1;
+        }
+        self.movie.frames.push(buttons);
+        apply(cpu, buttons);
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a movie's input to the controllers.
+pub struct Player<'a> {
+    movie: &'a Movie,
+    start_frame: usize,
+    last_frame: Option<usize>,
+}
+
+impl<'a> Player<'a> {
+    /// Loads the movie's start state, if it has one. A power-on movie expects `cpu`
+    /// to have just been reset.
+    pub fn new(movie: &'a Movie, cpu: &mut CPU) -> Result<Self, String> {
+        if let Some(state) = &movie.start {
+            cpu.load_state(state)?;
+        }
+        Ok(Player {
+            movie,
+            start_frame: cpu.bus.ppu.frame_count(),
+            last_frame: None,
+        })
+    }
+
+    /// Call between instructions. Applies the buttons of a frame when it starts and
+    /// returns false once the movie has run out of frames.
+    pub fn update(&mut self, cpu: &mut CPU) -> bool {
+        let frame = cpu.bus.ppu.frame_count();
+        let index = frame.saturating_sub(self.start_frame);
+        let Some(buttons) = self.movie.frames.get(index) else {
+            return false;
+        };
+        if self.last_frame != Some(frame) {
+            self.last_frame = Some(frame);
+            apply(cpu, *buttons);
+        }
+        true
+    }
+}
+
+fn apply(cpu: &mut CPU, buttons: [JoypadButton; 2]) {
+    for (joypad, buttons) in cpu.bus.joypads.iter_mut().zip(buttons) {
+        joypad.set_buttons(buttons);
+    }
+}
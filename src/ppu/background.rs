@@ -0,0 +1,105 @@
+use crate::ppu::PPU;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+/// The background half of the rendering pipeline: the tile fetched for the next 8
+/// pixels, and the 16-bit shift registers holding the current and next tile. The
+/// PPU shifts them once per dot and reads the pixel at the fine X scroll.
+#[derive(Default)]
+pub struct Background {
+    next_tile: u8,
+    next_palette: u8,
+    next_pattern_lo: u8,
+    next_pattern_hi: u8,
+
+    pattern_lo: u16,
+    pattern_hi: u16,
+    palette_lo: u16,
+    palette_hi: u16,
+}
+
+impl Background {
+    pub fn shift(&mut self) {
+        self.pattern_lo <<= 1;
+        self.pattern_hi <<= 1;
+        self.palette_lo <<= 1;
+        self.palette_hi <<= 1;
+    }
+
+    /// Moves the fetched tile into the low halves of the shift registers. The
+    /// palette is the same for all 8 pixels, so its bits are spread over a byte.
+    pub fn reload(&mut self) {
+        let spread = |bit: u8| {
+            if self.next_palette & bit != 0 {
+                0xFF
+            } else {
+                0
+            }
+        };
+        self.pattern_lo = (self.pattern_lo & 0xFF00) | self.next_pattern_lo as u16;
+        self.pattern_hi = (self.pattern_hi & 0xFF00) | self.next_pattern_hi as u16;
+        self.palette_lo = (self.palette_lo & 0xFF00) | spread(0b01);
+        self.palette_hi = (self.palette_hi & 0xFF00) | spread(0b10);
+    }
+
+    /// The 2-bit pixel and palette number `fine_x` pixels into the current tile.
+    pub fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 0x8000 >> fine_x;
+        let pick = |register: u16| (register & bit != 0) as u8;
+        (
+            pick(self.pattern_hi) << 1 | pick(self.pattern_lo),
+            pick(self.palette_hi) << 1 | pick(self.palette_lo),
+        )
+    }
+}
+
+impl PPU {
+    /// Runs one dot of the background fetches: a nametable byte, an attribute byte
+    /// and the two pattern planes every 8 dots, then moving on to the next tile.
+    pub(super) fn fetch_background(&mut self) {
+        let v = self.registers.scroll.v;
+        match (self.cycles - 1) % 8 {
+            0 => {
+                self.background.reload();
+                self.background.next_tile = self.peek(0x2000 | (v & 0x0FFF));
+            }
+            2 => {
+                let addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                let shift = ((v >> 4) & 0b100) | (v & 0b10);
+                self.background.next_palette = (self.peek(addr) >> shift) & 0b11;
+            }
+            4 => self.background.next_pattern_lo = self.read_chr(self.pattern_addr(v)),
+            6 => self.background.next_pattern_hi = self.read_chr(self.pattern_addr(v) + 8),
+            7 => self.registers.scroll.increment_coarse_x(),
+            _ => {}
+        }
+    }
+
+    fn pattern_addr(&self, v: u16) -> u16 {
+        let fine_y = (v >> 12) & 0b111;
+        self.registers.ctrl.bknd_pattern_addr() + self.background.next_tile as u16 * 16 + fine_y
+    }
+}
+
+impl Snapshot for Background {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.next_tile);
+        state.u8(self.next_palette);
+        state.u8(self.next_pattern_lo);
+        state.u8(self.next_pattern_hi);
+        state.u16(self.pattern_lo);
+        state.u16(self.pattern_hi);
+        state.u16(self.palette_lo);
+        state.u16(self.palette_hi);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.next_tile = state.u8();
+        self.next_palette = state.u8();
+        self.next_pattern_lo = state.u8();
+        self.next_pattern_hi = state.u8();
+        self.pattern_lo = state.u16();
+        self.pattern_hi = state.u16();
+        self.palette_lo = state.u16();
+        self.palette_hi = state.u16();
+    }
+}
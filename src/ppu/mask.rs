@@ -59,6 +59,11 @@ impl MaskReg {
         result
     }
 
+    /// The emphasis bits shifted down: red in bit 0, green in bit 1, blue in bit 2.
+    pub fn emphasis(&self) -> u8 {
+        self.bits >> 5
+    }
+
     pub fn update(&mut self, data: u8) {
         self.bits = data;
     }
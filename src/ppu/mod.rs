@@ -1,24 +1,44 @@
-mod addr;
+mod background;
 mod ctrl;
 mod mask;
 mod registers;
 mod scroll;
+mod sprites;
 mod status;
 
+use crate::bus::mapper::{Mapper0, SharedMapper};
 use crate::rom::Mirroring;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
 
+use background::Background;
 use registers::Registers;
+use sprites::Sprites;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+const DOTS_PER_LINE: usize = 341;
+const PRE_RENDER_LINE: u16 = 261;
 
 pub struct PPU {
-    pub vram: [u8; 0x800],
+    /// The console's 2K of nametable RAM, followed by the 2K four-screen
+    /// cartridges add for nametables 2 and 3.
+    pub vram: [u8; 0x1000],
     pub palette_table: [u8; 0x20],
-    pub oam_data: [u8; 0x100],
-    pub chr_rom: Vec<u8>,
-    pub mirroring: Mirroring,
+    pub mapper: SharedMapper,
     pub registers: Registers,
 
     scanline: u16,
     cycles: usize,
+    frame_count: usize,
+
+    background: Background,
+    sprites: Sprites,
+    /// The frame being drawn and the last completed one, see `frame`.
+    back_buffer: Vec<u16>,
+    front_buffer: Vec<u16>,
 }
 
 impl PPU {
@@ -26,30 +46,53 @@ impl PPU {
         PPU::new(vec![0; 2048], Mirroring::Horizontal)
     }
 
+    /// Standalone PPU backed by an NROM cartridge with the given CHR data.
     pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> PPU {
-        PPU {
-            vram: [0; 0x800],
+        PPU::with_mapper(Rc::new(RefCell::new(Mapper0::new(
+            vec![],
+            chr_rom,
             mirroring,
-            chr_rom: chr_rom,
+        ))))
+    }
+
+    pub fn with_mapper(mapper: SharedMapper) -> PPU {
+        PPU {
+            vram: [0; 0x1000],
+            mapper,
             palette_table: [0; 0x20],
-            oam_data: [0; 0x100],
             registers: Registers::new(),
 
             cycles: 0,
             scanline: 0,
+            frame_count: 0,
+
+            background: Background::default(),
+            sprites: Sprites::default(),
+            back_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            front_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
         }
     }
 
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.borrow().mirroring()
+    }
+
+    pub fn read_chr(&self, addr: u16) -> u8 {
+        self.mapper.borrow().read_chr_byte(addr)
+    }
+
     pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
         let mirrored_vram = addr & 0b10111111111111;
         let vram_index = mirrored_vram - 0x2000;
         let name_table = vram_index / 0x400;
 
-        match (&self.mirroring, name_table) {
+        match (self.mirroring(), name_table) {
             (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_index - 0x800,
             (Mirroring::Horizontal, 1) => vram_index - 0x400,
             (Mirroring::Horizontal, 2) => vram_index - 0x400,
             (Mirroring::Horizontal, 3) => vram_index - 0x800,
+            (Mirroring::SingleScreenLower, _) => vram_index % 0x400,
+            (Mirroring::SingleScreenUpper, _) => vram_index % 0x400 + 0x400,
             _ => vram_index,
         }
     }
@@ -62,92 +105,275 @@ impl PPU {
     }
 
     pub fn read_data(&mut self) -> u8 {
-        let addr = self.registers.addr.get();
+        let addr = self.registers.scroll.addr();
+        let result = self.peek_data();
         self.registers
-            .addr
+            .scroll
             .increment(self.registers.ctrl.vram_addr_increment());
-        println!("PPU: Reading data from addr: {:#X}", addr);
+        log::trace!("PPU: Reading data from addr: {:#X}", addr);
+        // palette reads skip the buffer, everything below goes through it
+        if addr < 0x3F00 {
+            self.registers.internal_data_buf = self.peek(addr);
+        }
+        result
+    }
+
+    /// What a $2007 read would return, without advancing the address or refilling
+    /// the read buffer.
+    pub fn peek_data(&self) -> u8 {
+        let addr = self.registers.scroll.addr();
         match addr {
-            0x0000..=0x1FFF => {
-                let result = self.registers.internal_data_buf;
-                self.registers.internal_data_buf = self.chr_rom[addr as usize];
-                result
-            }
-            0x2000..=0x2FFF => {
-                let result = self.registers.internal_data_buf;
-                let mirrored_addr = self.mirror_vram_addr(addr);
-                self.registers.internal_data_buf = self.vram[mirrored_addr as usize];
-                result
-            }
-            0x3000..=0x3EFF => unimplemented!(),
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => {
-                let mirrored_addr = addr - 0x10;
-                self.palette_table[(mirrored_addr - 0x3F00) as usize]
+            0x3F00..=0x3FFF => self.peek(addr),
+            _ => self.registers.internal_data_buf,
+        }
+    }
+
+    /// Reads the PPU address space ($0000-$3FFF) directly, for memory viewers.
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => self.mapper.borrow().peek_chr_byte(addr),
+            0x2000..=0x3EFF => self.vram[self.mirror_vram_addr(addr) as usize],
+            _ => {
+                // $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries below them
+                let index = (addr & 0x1F) as usize;
+                match index {
+                    0x10 | 0x14 | 0x18 | 0x1C => self.palette_table[index - 0x10],
+                    _ => self.palette_table[index],
+                }
             }
-            0x3F00..=0x3FFF => self.palette_table[(addr - 0x3F00) as usize],
-            _ => panic!("Unimplemented PPU read at address: {:#X}", addr),
+        }
+    }
+
+    /// What a CPU read of register `addr` ($2000-$2007) would return. Write-only
+    /// registers read as 0, like on the bus.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr & 0b111 {
+            2 => self.registers.peek_status(),
+            4 => self.registers.read_oam_data(),
+            7 => self.peek_data(),
+            _ => 0,
         }
     }
 
     pub fn write_to_data(&mut self, value: u8) {
-        let addr = self.registers.addr.get();
-        println!("PPU: Writing data to addr: {:#X}", addr);
+        let addr = self.registers.scroll.addr();
+        log::trace!("PPU: Writing data to addr: {:#X}", addr);
         match addr {
-            0x0000..=0x1FFF => println!("Attempt to write to chr rom space: {}", addr),
+            0x0000..=0x1FFF => self.mapper.borrow_mut().write_chr_byte(addr, value),
             0x2000..=0x2FFF => {
                 let mirrored_addr = self.mirror_vram_addr(addr);
                 self.vram[mirrored_addr as usize] = value;
             }
-            0x3000..=0x3EFF => unimplemented!(),
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => {
-                let addr_mirror = addr - 0x10;
-                self.palette_table[(addr_mirror - 0x3F00) as usize] = value;
+            0x3000..=0x3EFF => {
+                // mirror of $2000-$2EFF
+                let mirrored_addr = self.mirror_vram_addr(addr - 0x1000);
+                self.vram[mirrored_addr as usize] = value;
             }
             0x3F00..=0x3FFF => {
-                self.palette_table[(addr - 0x3F00) as usize] = value;
+                // the 32 entries repeat up to $3FFF, and $3F10/$3F14/$3F18/$3F1C
+                // mirror the backdrop entries below them
+                let index = (addr & 0x1F) as usize;
+                match index {
+                    0x10 | 0x14 | 0x18 | 0x1C => self.palette_table[index - 0x10] = value,
+                    _ => self.palette_table[index] = value,
+                }
             }
             _ => panic!("Unexpected write to mirrored space: {}", addr),
         }
         self.registers
-            .addr
+            .scroll
             .increment(self.registers.ctrl.vram_addr_increment());
     }
 
+    /// Advances the PPU by `cycles` dots, returning true if a frame was completed.
     pub fn tick(&mut self, cycles: u8) -> bool {
-        self.cycles += cycles as usize;
-        if self.cycles >= 341 {
-            if self.is_sprite_0_hit(self.cycles) {
-                self.registers.status.set_sprite_zero_hit(true);
-            }
+        let mut frame_done = false;
+        for _ in 0..cycles {
+            frame_done |= self.step();
+        }
+        frame_done
+    }
+
+    fn step(&mut self) -> bool {
+        if self.scanline == PRE_RENDER_LINE && self.cycles == 1 {
+            // vblank ends
+            self.registers.nmi_interrupt = None;
+            self.registers.status.set_sprite_zero_hit(false);
+            self.registers.status.set_sprite_overflow(false);
+            self.registers.status.reset_vblank_status();
+        }
+        if self.is_rendering() && (self.scanline < 240 || self.scanline == PRE_RENDER_LINE) {
+            self.render_dot();
+        }
+        if self.scanline < 240 && (1..=SCREEN_WIDTH).contains(&self.cycles) {
+            self.output_pixel();
+        }
+
+        self.cycles += 1;
+        if Some(self.cycles) == self.a12_rise_dot() {
+            self.mapper.borrow_mut().signal_scanline();
+        }
 
-            self.cycles = self.cycles - 341;
+        if self.cycles >= DOTS_PER_LINE {
+            self.cycles -= DOTS_PER_LINE;
             self.scanline += 1;
 
             if self.scanline == 241 {
+                std::mem::swap(&mut self.back_buffer, &mut self.front_buffer);
                 self.registers.status.set_vblank_status(true);
-                self.registers.status.set_sprite_zero_hit(false);
                 if self.registers.ctrl.generate_vblank_nmi() {
                     self.registers.nmi_interrupt = Some(1);
                 }
             }
 
-            if self.scanline >= 262 {
+            if self.scanline > PRE_RENDER_LINE {
                 self.scanline = 0;
-                self.registers.nmi_interrupt = None;
-                self.registers.status.set_sprite_zero_hit(false);
-                self.registers.status.reset_vblank_status();
+                self.frame_count += 1;
                 return true;
             }
         }
-        return false;
+        false
     }
+
+    fn is_rendering(&self) -> bool {
+        self.registers.mask.show_background() || self.registers.mask.show_sprites()
+    }
+
+    /// The fetches and address updates of one dot on a rendered line.
+    fn render_dot(&mut self) {
+        let dot = self.cycles;
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.background.shift();
+        }
+        if (1..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.fetch_background();
+        }
+
+        match dot {
+            256 => self.registers.scroll.increment_y(),
+            257 => {
+                self.registers.scroll.copy_horizontal();
+                if self.scanline == PRE_RENDER_LINE {
+                    self.sprites.clear();
+                } else {
+                    self.evaluate_sprites();
+                }
+            }
+            280..=304 if self.scanline == PRE_RENDER_LINE => self.registers.scroll.copy_vertical(),
+            _ => {}
+        }
+    }
+
+    /// Combines the background and sprite pixels under the current dot, as masked
+    /// by PPUMASK.
+    fn output_pixel(&mut self) {
+        let x = self.cycles - 1;
+        let mask = self.registers.mask;
+        let palette_addr = if self.is_rendering() {
+            let show_background =
+                mask.show_background() && (x >= 8 || mask.leftmost_8pxl_background());
+            let show_sprites = mask.show_sprites() && (x >= 8 || mask.leftmost_8pxl_sprite());
+
+            let (bg_pixel, bg_palette) = match self.background.pixel(self.registers.scroll.x) {
+                (pixel, palette) if show_background => (pixel, palette),
+                _ => (0, 0),
+            };
+            let sprite = self.sprites.pixel(x).filter(|_| show_sprites);
+            if bg_pixel != 0 && show_sprites && x != 255 && self.sprites.sprite_zero_at(x) {
+                self.registers.status.set_sprite_zero_hit(true);
+            }
+
+            match sprite {
+                Some(sprite) if !sprite.behind_background || bg_pixel == 0 => {
+                    0x3F10 | (sprite.palette << 2 | sprite.pixel) as u16
+                }
+                _ if bg_pixel == 0 => 0x3F00,
+                _ => 0x3F00 | (bg_palette << 2 | bg_pixel) as u16,
+            }
+        } else if self.registers.scroll.addr() >= 0x3F00 {
+            // with rendering off, the backdrop comes from wherever v points into
+            // the palette
+            self.registers.scroll.addr()
+        } else {
+            0x3F00
+        };
+
+        let mut color = self.peek(palette_addr) & 0x3F;
+        if mask.is_grayscale() {
+            color &= 0x30;
+        }
+        self.back_buffer[self.scanline as usize * SCREEN_WIDTH + x] =
+            (mask.emphasis() as u16) << 6 | color as u16;
+    }
+
+    /// The last completed frame, `SCREEN_WIDTH` x `SCREEN_HEIGHT` pixels row by row.
+    /// Each pixel is a NES color (0-63) with the PPUMASK emphasis bits above it, an
+    /// index into `render::palette::EMPHASIS_PALETTE`.
+    pub fn frame(&self) -> &[u16] {
+        &self.front_buffer
+    }
+
+    /// Dot at which PPU A12 rises on a rendered line: when the sprite pattern fetches
+    /// (dots 257-320) come from $1000 while the background uses $0000, or at the
+    /// background prefetch (dots 321-336) for the opposite arrangement.
+    fn a12_rise_dot(&self) -> Option<usize> {
+        if !self.is_rendering() || (self.scanline >= 240 && self.scanline != PRE_RENDER_LINE) {
+            return None;
+        }
+
+        let bknd = self.registers.ctrl.bknd_pattern_addr();
+        let sprt = self.registers.ctrl.sprt_pattern_addr();
+        match (bknd, sprt) {
+            (0x0000, 0x1000) => Some(260),
+            (0x1000, 0x0000) => Some(324),
+            _ => None,
+        }
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// Dot (PPU cycle) within the current scanline.
+    pub fn dot(&self) -> usize {
+        self.cycles
+    }
+
+    /// Number of frames completed since power-on.
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
     pub fn poll_nmi_interrupt(&mut self) -> Option<u8> {
         self.registers.nmi_interrupt.take()
     }
 
-    fn is_sprite_0_hit(&self, cycle: usize) -> bool {
-        let y = self.oam_data[0] as usize;
-        let x = self.oam_data[3] as usize;
-        (y == self.scanline as usize) && x <= cycle && self.registers.mask.show_sprites()
+    pub fn nmi_pending(&self) -> bool {
+        self.registers.nmi_interrupt.is_some()
+    }
+}
+
+impl Snapshot for PPU {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.vram);
+        state.bytes(&self.palette_table);
+        self.registers.save(state);
+        state.u16(self.scanline);
+        state.usize(self.cycles);
+        state.usize(self.frame_count);
+        self.background.save(state);
+        self.sprites.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        state.bytes_into(&mut self.vram);
+        state.bytes_into(&mut self.palette_table);
+        self.registers.load(state);
+        self.scanline = state.u16();
+        self.cycles = state.usize();
+        self.frame_count = state.usize();
+        self.background.load(state);
+        self.sprites.load(state);
     }
 }
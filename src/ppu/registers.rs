@@ -1,14 +1,13 @@
-use crate::ppu::addr::AddrReg;
 use crate::ppu::ctrl::CtrlReg;
 use crate::ppu::mask::MaskReg;
 use crate::ppu::scroll::ScrollReg;
 use crate::ppu::status::StatusReg;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
 
 pub struct Registers {
     pub ctrl: CtrlReg,
     pub mask: MaskReg,
     pub status: StatusReg,
-    pub addr: AddrReg,
     pub scroll: ScrollReg,
     pub oam_addr: u8,
     pub oam_data: [u8; 0x100],
@@ -22,7 +21,6 @@ impl Registers {
             ctrl: CtrlReg::new(),
             mask: MaskReg::new(),
             status: StatusReg::new(),
-            addr: AddrReg::new(),
             scroll: ScrollReg::new(),
             oam_addr: 0,
             oam_data: [0; 64 * 4],
@@ -32,11 +30,11 @@ impl Registers {
     }
 
     pub fn write_to_ppu_addr(&mut self, value: u8) {
-        self.addr.update(value);
+        self.scroll.write_addr(value);
     }
 
     pub fn write_to_scroll(&mut self, value: u8) {
-        self.scroll.write(value);
+        self.scroll.write_scroll(value);
     }
 
     pub fn write_to_mask(&mut self, value: u8) {
@@ -52,23 +50,52 @@ impl Registers {
         self.oam_addr = addr;
     }
 
-    pub fn read_oam_data(&mut self) -> u8 {
+    pub fn read_oam_data(&self) -> u8 {
         self.oam_data[self.oam_addr as usize]
     }
 
     pub fn write_control(&mut self, value: u8) {
         let before_nmi_status = self.ctrl.generate_vblank_nmi();
         self.ctrl.update(value);
+        self.scroll.write_nametable(value);
         if !before_nmi_status && self.ctrl.generate_vblank_nmi() && self.status.is_in_vblank() {
             self.nmi_interrupt = Some(1);
         }
     }
 
     pub fn read_status(&mut self) -> u8 {
-        let data = self.status.snapshot();
+        let data = self.peek_status();
         self.status.reset_vblank_status();
-        self.addr.reset_latch();
         self.scroll.reset_latch();
         data
     }
+
+    /// $2002 without clearing vblank or the write latches.
+    pub fn peek_status(&self) -> u8 {
+        self.status.snapshot()
+    }
+}
+
+impl Snapshot for Registers {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.ctrl.bits());
+        state.u8(self.mask.bits());
+        state.u8(self.status.bits());
+        self.scroll.save(state);
+        state.u8(self.oam_addr);
+        state.bytes(&self.oam_data);
+        state.bool(self.nmi_interrupt.is_some());
+        state.u8(self.internal_data_buf);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.ctrl = CtrlReg::from_bits_truncate(state.u8());
+        self.mask = MaskReg::from_bits_truncate(state.u8());
+        self.status = StatusReg::from_bits_truncate(state.u8());
+        self.scroll.load(state);
+        self.oam_addr = state.u8();
+        state.bytes_into(&mut self.oam_data);
+        self.nmi_interrupt = if state.bool() { Some(1) } else { None };
+        self.internal_data_buf = state.u8();
+    }
 }
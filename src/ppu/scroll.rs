@@ -1,28 +1,137 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE: u16 = 0x0C00;
+const FINE_Y: u16 = 0x7000;
+
+/// The PPU's internal scroll registers, as documented by loopy. `$2005` and `$2006`
+/// both write into `t` and share the write toggle `w`; rendering copies `t` into
+/// `v` at the start of every line and frame, and `v` is also the `$2007` address.
+///
+/// ```text
+/// yyy NN YYYYY XXXXX
+/// |   |  |     +----- coarse X scroll
+/// |   |  +----------- coarse Y scroll
+/// |   +-------------- nametable select
+/// +------------------ fine Y scroll
+/// ```
 pub struct ScrollReg {
-    pub scroll_x: u8,
-    pub scroll_y: u8,
-    pub latch: bool,
+    /// Current VRAM address.
+    pub v: u16,
+    /// Temporary VRAM address, the top left corner of the screen.
+    pub t: u16,
+    /// Fine X scroll.
+    pub x: u8,
+    /// Write toggle, set after the first of two writes.
+    pub w: bool,
 }
 
 impl ScrollReg {
     pub fn new() -> Self {
         ScrollReg {
-            scroll_x: 0,
-            scroll_y: 0,
-            latch: false,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+        }
+    }
+
+    /// The address `$2007` reads and writes.
+    pub fn addr(&self) -> u16 {
+        self.v & 0x3FFF
+    }
+
+    /// `$2000`: the nametable select bits.
+    pub fn write_nametable(&mut self, ctrl: u8) {
+        self.t = (self.t & !NAMETABLE) | ((ctrl as u16 & 0b11) << 10);
+    }
+
+    /// `$2005`: X scroll first, then Y scroll.
+    pub fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (data >> 3) as u16;
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !(COARSE_Y | FINE_Y)) | ((data as u16 & 0xF8) << 2);
+            self.t |= (data as u16 & 0b111) << 12;
         }
+        self.w = !self.w;
     }
 
-    pub fn write(&mut self, data: u8) {
-        if !self.latch {
-            self.scroll_x = data;
+    /// `$2006`: high byte first, then the low byte, which also loads `v`.
+    pub fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
         } else {
-            self.scroll_y = data;
+            self.t = (self.t & 0xFF00) | data as u16;
+            self.v = self.t;
         }
-        self.latch = !self.latch;
+        self.w = !self.w;
+    }
+
+    /// Steps `v` after a `$2007` access.
+    pub fn increment(&mut self, inc: u8) {
+        self.v = self.v.wrapping_add(inc as u16) & 0x7FFF;
     }
 
     pub fn reset_latch(&mut self) {
-        self.latch = false;
+        self.w = false;
+    }
+
+    pub fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            // wrap into the horizontally adjacent nametable
+            self.v &= !COARSE_X;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    pub fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !FINE_Y;
+        let coarse_y = match (self.v & COARSE_Y) >> 5 {
+            // row 29 is the last one, the attribute table follows
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    /// Dot 257: back to the left edge of the screen.
+    pub fn copy_horizontal(&mut self) {
+        const HORIZONTAL: u16 = COARSE_X | 0x0400;
+        self.v = (self.v & !HORIZONTAL) | (self.t & HORIZONTAL);
+    }
+
+    /// Dots 280-304 of the pre-render line: back to the top of the screen.
+    pub fn copy_vertical(&mut self) {
+        const VERTICAL: u16 = FINE_Y | COARSE_Y | 0x0800;
+        self.v = (self.v & !VERTICAL) | (self.t & VERTICAL);
+    }
+}
+
+impl Snapshot for ScrollReg {
+    fn save(&self, state: &mut StateWriter) {
+        state.u16(self.v);
+        state.bool(self.w);
+        state.u16(self.t);
+        state.u8(self.x);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.v = state.u16();
+        self.w = state.bool();
+        self.t = state.u16();
+        self.x = state.u8();
     }
 }
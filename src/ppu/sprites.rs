@@ -0,0 +1,167 @@
+use crate::ppu::PPU;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const SPRITES_PER_LINE: usize = 8;
+
+const PRIORITY_BEHIND: u8 = 0b0010_0000;
+const FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// One of the eight sprite output units, loaded with a sprite's row of pixels for
+/// the next scanline. The pattern bytes are already flipped horizontally.
+#[derive(Default, Clone, Copy)]
+struct SpriteUnit {
+    x: u8,
+    attributes: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+}
+
+impl SpriteUnit {
+    fn pixel(&self, x: usize) -> u8 {
+        let Some(column) = x.checked_sub(self.x as usize).filter(|column| *column < 8) else {
+            return 0;
+        };
+        let bit = 0x80 >> column;
+        ((self.pattern_hi & bit != 0) as u8) << 1 | (self.pattern_lo & bit != 0) as u8
+    }
+}
+
+/// The frontmost opaque sprite pixel at a dot.
+pub struct SpritePixel {
+    pub pixel: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+}
+
+#[derive(Default)]
+pub struct Sprites {
+    units: [SpriteUnit; SPRITES_PER_LINE],
+    count: usize,
+    /// Whether the first unit holds OAM entry 0, which can trigger a sprite 0 hit.
+    has_sprite_zero: bool,
+}
+
+impl Sprites {
+    pub fn clear(&mut self) {
+        self.count = 0;
+        self.has_sprite_zero = false;
+    }
+
+    /// The opaque pixel of the sprite with the lowest OAM index at `x`. Its
+    /// priority bit alone decides whether it shows over the background, even when
+    /// a sprite behind it would have been in front.
+    pub fn pixel(&self, x: usize) -> Option<SpritePixel> {
+        self.units[..self.count].iter().find_map(|unit| {
+            let pixel = unit.pixel(x);
+            (pixel != 0).then_some(SpritePixel {
+                pixel,
+                palette: unit.attributes & 0b11,
+                behind_background: unit.attributes & PRIORITY_BEHIND != 0,
+            })
+        })
+    }
+
+    /// Whether sprite 0 has an opaque pixel at `x`.
+    pub fn sprite_zero_at(&self, x: usize) -> bool {
+        self.has_sprite_zero && self.units[0].pixel(x) != 0
+    }
+}
+
+impl PPU {
+    /// Finds the first eight sprites in OAM that cover the next scanline and loads
+    /// their rows into the sprite units, then keeps scanning for a ninth one to set
+    /// the overflow flag.
+    pub(super) fn evaluate_sprites(&mut self) {
+        let height = self.registers.ctrl.sprite_size() as usize;
+        // OAM holds the Y coordinate minus one, so the row on the next line is
+        // counted from the current one
+        let row_of = |y: u8| {
+            (self.scanline as usize)
+                .checked_sub(y as usize)
+                .filter(|row| *row < height)
+        };
+
+        let oam = &self.registers.oam_data;
+        let mut sprites = Sprites::default();
+        let mut n = 0;
+        while n < 64 && sprites.count < SPRITES_PER_LINE {
+            let entry = &oam[n * 4..n * 4 + 4];
+            if let Some(row) = row_of(entry[0]) {
+                sprites.units[sprites.count] = self.load_sprite(entry, row);
+                sprites.count += 1;
+                sprites.has_sprite_zero |= n == 0;
+            }
+            n += 1;
+        }
+
+        // the hardware bug: after eight hits, the byte read as the Y coordinate
+        // moves along with the entry, so tiles, attributes and X get compared too
+        let mut m = 0;
+        while n < 64 {
+            if row_of(oam[n * 4 + m]).is_some() {
+                self.registers.status.set_sprite_overflow(true);
+                break;
+            }
+            n += 1;
+            m = (m + 1) % 4;
+        }
+
+        self.sprites = sprites;
+    }
+
+    fn load_sprite(&self, entry: &[u8], row: usize) -> SpriteUnit {
+        let attributes = entry[2];
+        let height = self.registers.ctrl.sprite_size() as usize;
+        let row = if attributes & FLIP_VERTICAL != 0 {
+            height - 1 - row
+        } else {
+            row
+        };
+
+        // 8x16 sprites take the pattern table from bit 0 of the tile number and
+        // stack two consecutive tiles
+        let (bank, tile) = if height == 16 {
+            ((entry[1] as u16 & 1) * 0x1000, entry[1] as u16 & 0xFE)
+        } else {
+            (self.registers.ctrl.sprt_pattern_addr(), entry[1] as u16)
+        };
+        let addr = bank + (tile + row as u16 / 8) * 16 + row as u16 % 8;
+
+        let (mut lo, mut hi) = (self.read_chr(addr), self.read_chr(addr + 8));
+        if attributes & FLIP_HORIZONTAL != 0 {
+            lo = lo.reverse_bits();
+            hi = hi.reverse_bits();
+        }
+        SpriteUnit {
+            x: entry[3],
+            attributes,
+            pattern_lo: lo,
+            pattern_hi: hi,
+        }
+    }
+}
+
+impl Snapshot for Sprites {
+    fn save(&self, state: &mut StateWriter) {
+        state.usize(self.count);
+        for unit in &self.units {
+            state.u8(unit.x);
+            state.u8(unit.attributes);
+            state.u8(unit.pattern_lo);
+            state.u8(unit.pattern_hi);
+        }
+        state.bool(self.has_sprite_zero);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.count = state.usize().min(SPRITES_PER_LINE);
+        for unit in &mut self.units {
+            unit.x = state.u8();
+            unit.attributes = state.u8();
+            unit.pattern_lo = state.u8();
+            unit.pattern_hi = state.u8();
+        }
+        self.has_sprite_zero = state.bool();
+    }
+}
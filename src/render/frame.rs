@@ -1,23 +1,76 @@
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::io::{self, Write};
+
+/// An RGB24 image: 3 bytes (red, green, blue) per pixel, rows top to bottom with
+/// no padding between them. Normally the 256x240 picture of the NES.
 pub struct Frame {
+    width: usize,
+    height: usize,
     pub data: Vec<u8>,
 }
 
 impl Frame {
-    const WIDTH: usize = 256 * 2;
-    const HIGHT: usize = 240;
+    pub const WIDTH: usize = SCREEN_WIDTH;
+    pub const HEIGHT: usize = SCREEN_HEIGHT;
 
     pub fn new() -> Self {
+        Frame::with_size(Frame::WIDTH, Frame::HEIGHT)
+    }
+
+    /// A frame of another size, for debug views.
+    pub fn with_size(width: usize, height: usize) -> Self {
         Frame {
-            data: vec![0; (Frame::WIDTH) * (Frame::HIGHT) * 3],
+            width,
+            height,
+            data: vec![0; width * height * 3],
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Bytes per row.
+    pub fn pitch(&self) -> usize {
+        self.width * 3
+    }
+
     pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
-        let base = y * 3 * Frame::WIDTH + x * 3;
-        if base + 2 < self.data.len() {
-            self.data[base] = rgb.0;
-            self.data[base + 1] = rgb.1;
-            self.data[base + 2] = rgb.2;
+        if x >= self.width || y >= self.height {
+            return;
         }
+        let base = y * self.pitch() + x * 3;
+        self.data[base] = rgb.0;
+        self.data[base + 1] = rgb.1;
+        self.data[base + 2] = rgb.2;
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = y * self.pitch() + x * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+
+    /// FNV-1a hash of the pixels, stable across runs and platforms so it can be
+    /// stored in regression tests.
+    pub fn hash(&self) -> u64 {
+        self.data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Writes the frame as a binary PPM (P6) image.
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.data)
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
     }
 }
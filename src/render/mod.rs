@@ -1,215 +1,47 @@
 pub mod frame;
 pub mod palette;
 
-use crate::ppu::PPU;
-use crate::rom::Mirroring;
+use crate::ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH};
 use frame::Frame;
 
-fn bg_pallette(ppu: &PPU, attribute_table: &[u8], tile_column: usize, tile_row: usize) -> [u8; 4] {
-    let attr_table_idx = tile_row / 4 * 8 + tile_column / 4;
-    let attr_byte = attribute_table[attr_table_idx];
-
-    let pallet_idx = match (tile_column % 4 / 2, tile_row % 4 / 2) {
-        (0, 0) => attr_byte & 0b11,
-        (1, 0) => (attr_byte >> 2) & 0b11,
-        (0, 1) => (attr_byte >> 4) & 0b11,
-        (1, 1) => (attr_byte >> 6) & 0b11,
-        (_, _) => panic!("should not happen"),
-    };
-
-    let pallete_start: usize = 1 + (pallet_idx as usize) * 4;
-    [
-        ppu.palette_table[0],
-        ppu.palette_table[pallete_start],
-        ppu.palette_table[pallete_start + 1],
-        ppu.palette_table[pallete_start + 2],
-    ]
-}
-
-fn sprite_palette(ppu: &PPU, pallete_idx: u8) -> [u8; 4] {
-    let start = 0x11 + (pallete_idx * 4) as usize;
-    [
-        0,
-        ppu.palette_table[start],
-        ppu.palette_table[start + 1],
-        ppu.palette_table[start + 2],
-    ]
-}
-
-struct Rect {
-    x1: usize,
-    y1: usize,
-    x2: usize,
-    y2: usize,
-}
-
-impl Rect {
-    fn new(x1: usize, y1: usize, x2: usize, y2: usize) -> Self {
-        Rect {
-            x1: x1,
-            y1: y1,
-            x2: x2,
-            y2: y2,
-        }
-    }
-}
-
-fn render_name_table(
-    ppu: &PPU,
-    frame: &mut Frame,
-    name_table: &[u8],
-    view_port: Rect,
-    shift_x: isize,
-    shift_y: isize,
-) {
-    let bank = ppu.registers.ctrl.bknd_pattern_addr();
-
-    let attribute_table = &name_table[0x3c0..0x400];
-
-    for i in 0..0x3c0 {
-        let tile_column = i % 32;
-        let tile_row = i / 32;
-        let tile_idx = name_table[i] as u16;
-        let tile =
-            &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
-        let palette = bg_pallette(ppu, attribute_table, tile_column, tile_row);
-
-        for y in 0..=7 {
-            let mut upper = tile[y];
-            let mut lower = tile[y + 8];
-
-            for x in (0..=7).rev() {
-                let value = (1 & lower) << 1 | (1 & upper);
-                upper = upper >> 1;
-                lower = lower >> 1;
-                let rgb = match value {
-                    0 => palette::SYSTEM_PALLETE[ppu.palette_table[0] as usize],
-                    1 => palette::SYSTEM_PALLETE[palette[1] as usize],
-                    2 => palette::SYSTEM_PALLETE[palette[2] as usize],
-                    3 => palette::SYSTEM_PALLETE[palette[3] as usize],
-                    _ => panic!("can't be"),
-                };
-                let pixel_x = tile_column * 8 + x;
-                let pixel_y = tile_row * 8 + y;
-
-                if pixel_x >= view_port.x1
-                    && pixel_x < view_port.x2
-                    && pixel_y >= view_port.y1
-                    && pixel_y < view_port.y2
-                {
-                    frame.set_pixel(
-                        (shift_x + pixel_x as isize) as usize,
-                        (shift_y + pixel_y as isize) as usize,
-                        rgb,
-                    );
-                }
-            }
-        }
-    }
-}
+/// Size of the debug composite: the picture on the left, and the four nametables
+/// at half resolution on the right.
+pub const DEBUG_WIDTH: usize = SCREEN_WIDTH * 2;
+pub const DEBUG_HEIGHT: usize = SCREEN_HEIGHT;
 
+/// Copies the last frame the PPU completed into `frame`.
 pub fn render(ppu: &PPU, frame: &mut Frame) {
-    let scroll_x = (ppu.registers.scroll.scroll_x) as usize;
-    let scroll_y = (ppu.registers.scroll.scroll_y) as usize;
-
-    let (main_nametable, second_nametable) =
-        match (&ppu.mirroring, ppu.registers.ctrl.nametable_addr()) {
-            (Mirroring::Vertical, 0x2000)
-            | (Mirroring::Vertical, 0x2800)
-            | (Mirroring::Horizontal, 0x2000)
-            | (Mirroring::Horizontal, 0x2400) => (&ppu.vram[0..0x400], &ppu.vram[0x400..0x800]),
-            (Mirroring::Vertical, 0x2400)
-            | (Mirroring::Vertical, 0x2C00)
-            | (Mirroring::Horizontal, 0x2800)
-            | (Mirroring::Horizontal, 0x2C00) => (&ppu.vram[0x400..0x800], &ppu.vram[0..0x400]),
-            (_, _) => {
-                panic!("Not supported mirroring type {:?}", ppu.mirroring);
-            }
-        };
-
-    render_name_table(
-        ppu,
-        frame,
-        main_nametable,
-        Rect::new(scroll_x, scroll_y, 256, 240),
-        -(scroll_x as isize),
-        -(scroll_y as isize),
-    );
-    if scroll_x > 0 {
-        render_name_table(
-            ppu,
-            frame,
-            second_nametable,
-            Rect::new(0, 0, scroll_x, 240),
-            (256 - scroll_x) as isize,
-            0,
-        );
-    } else if scroll_y > 0 {
-        render_name_table(
-            ppu,
-            frame,
-            second_nametable,
-            Rect::new(0, 0, 256, scroll_y),
-            0,
-            (240 - scroll_y) as isize,
-        );
+    for (i, pixel) in ppu.frame().iter().enumerate() {
+        let rgb = palette::EMPHASIS_PALETTE[*pixel as usize];
+        frame.set_pixel(i % SCREEN_WIDTH, i / SCREEN_WIDTH, rgb);
     }
+}
 
-    for i in (0..ppu.oam_data.len()).step_by(4).rev() {
-        let tile_idx = ppu.oam_data[i + 1] as u16;
-        let tile_x = ppu.oam_data[i + 3] as usize;
-        let tile_y = ppu.oam_data[i] as usize;
-
-        let flip_vertical = if ppu.oam_data[i + 2] >> 7 & 1 == 1 {
-            true
-        } else {
-            false
-        };
-        let flip_horizontal = if ppu.oam_data[i + 2] >> 6 & 1 == 1 {
-            true
-        } else {
-            false
-        };
-        let pallette_idx = ppu.oam_data[i + 2] & 0b11;
-        let sprite_palette = sprite_palette(ppu, pallette_idx);
-        let bank: u16 = ppu.registers.ctrl.sprt_pattern_addr();
-
-        let tile =
-            &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
+/// Draws the debug composite into a `DEBUG_WIDTH` x `DEBUG_HEIGHT` frame. The
+/// nametables are drawn from the current VRAM, palettes and background pattern
+/// table, not from what the picture was rendered with.
+pub fn render_debug(ppu: &PPU, frame: &mut Frame) {
+    render(ppu, frame);
 
-        for y in 0..=7 {
-            let mut upper = tile[y];
-            let mut lower = tile[y + 8];
-            'ololo: for x in (0..=7).rev() {
-                let value = (1 & lower) << 1 | (1 & upper);
-                upper = upper >> 1;
-                lower = lower >> 1;
-                let rgb = match value {
-                    0 => continue 'ololo, // skip coloring the pixel
-                    1 => palette::SYSTEM_PALLETE[sprite_palette[1] as usize],
-                    2 => palette::SYSTEM_PALLETE[sprite_palette[2] as usize],
-                    3 => palette::SYSTEM_PALLETE[sprite_palette[3] as usize],
-                    _ => panic!("can't be"),
-                };
-                match (flip_horizontal, flip_vertical) {
-                    (false, false) => {
-                        frame.set_pixel(tile_x + x, tile_y + y, rgb);
-                        frame.set_pixel(tile_x + x, tile_y + y + 250, rgb);
-                    }
-                    (true, false) => {
-                        frame.set_pixel(tile_x + 7 - x, tile_y + y, rgb);
-                        frame.set_pixel(tile_x + 7 - x, tile_y + y + 250, rgb);
-                    }
-                    (false, true) => {
-                        frame.set_pixel(tile_x + x, tile_y + 7 - y, rgb);
-                        frame.set_pixel(tile_x + x, tile_y + 7 - y + 250, rgb);
-                    }
-                    (true, true) => {
-                        frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y, rgb);
-                        frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y + 250, rgb);
-                    }
-                }
-            }
+    let bank = ppu.registers.ctrl.bknd_pattern_addr();
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            // every other pixel of the 512x480 nametable area
+            let (nt_x, nt_y) = (x * 2 % SCREEN_WIDTH, y * 2 % SCREEN_HEIGHT);
+            let nametable = 0x2000 + 0x400 * (x * 2 / SCREEN_WIDTH + y * 2 / SCREEN_HEIGHT * 2);
+            let (column, row) = (nt_x / 8, nt_y / 8);
+
+            let tile = ppu.peek((nametable + row * 32 + column) as u16) as u16;
+            let attribute = ppu.peek((nametable + 0x3C0 + row / 4 * 8 + column / 4) as u16);
+            let palette = (attribute >> ((row & 2) << 1 | (column & 2))) & 0b11;
+
+            let addr = bank + tile * 16 + (nt_y % 8) as u16;
+            let bit = 7 - nt_x % 8;
+            let pixel = (ppu.peek(addr + 8) >> bit & 1) << 1 | ppu.peek(addr) >> bit & 1;
+            let palette_index = if pixel == 0 { 0 } else { palette << 2 | pixel };
+
+            let color = ppu.peek(0x3F00 | palette_index as u16) & 0x3F;
+            frame.set_pixel(SCREEN_WIDTH + x, y, palette::SYSTEM_PALLETE[color as usize]);
         }
     }
 }
@@ -15,3 +15,32 @@ pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
     (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA), 
     (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
 ];
+
+/// `SYSTEM_PALLETE` under each combination of the PPUMASK color emphasis bits, at
+/// index `emphasis << 6 | color` (see `PPU::frame`). Emphasizing a color darkens
+/// the other two channels to about 82%.
+pub static EMPHASIS_PALETTE: [(u8, u8, u8); 512] = emphasis_palette();
+
+const fn emphasis_palette() -> [(u8, u8, u8); 512] {
+    const fn attenuate(channel: u8, emphasis: usize, own_bit: usize) -> u8 {
+        if emphasis & !own_bit != 0 {
+            (channel as u16 * 209 / 256) as u8
+        } else {
+            channel
+        }
+    }
+
+    let mut palette = [(0, 0, 0); 512];
+    let mut i = 0;
+    while i < palette.len() {
+        let (r, g, b) = SYSTEM_PALLETE[i % 64];
+        let emphasis = i >> 6;
+        palette[i] = (
+            attenuate(r, emphasis, 0b001),
+            attenuate(g, emphasis, 0b010),
+            attenuate(b, emphasis, 0b100),
+        );
+        i += 1;
+    }
+    palette
+}
@@ -1,34 +1,188 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use log::debug;
+use std::fmt;
+use std::io::{self, Read};
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+// iNES 1.0 has no RAM sizes, cartridges that have RAM almost always have 8K of it
+const INES_PRG_RAM_SIZE: usize = 8192;
+const INES_CHR_RAM_SIZE: usize = 8192;
+
+/// Why a ROM image could not be loaded.
+#[derive(Debug)]
+pub enum RomError {
+    /// Shorter than the 16-byte header.
+    TooShort {
+        len: usize,
+    },
+    /// Does not start with `NES<EOF>`.
+    BadMagic,
+    /// An archaic iNES 0.7 header or one with garbage in byte 7.
+    UnsupportedHeader,
+    TruncatedTrainer,
+    TruncatedPrg {
+        expected: usize,
+        found: usize,
+    },
+    TruncatedChr {
+        expected: usize,
+        found: usize,
+    },
+    MissingPrg,
+    UnsupportedMapper(u16),
+    Io(io::Error),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooShort { len } => {
+                write!(f, "file is {} bytes, too short for an NES header", len)
+            }
+            RomError::BadMagic => write!(f, "not an NES file"),
+            RomError::UnsupportedHeader => {
+                write!(f, "only iNES 1.0 and NES 2.0 headers are supported")
+            }
+            RomError::TruncatedTrainer => write!(f, "trainer is truncated"),
+            RomError::TruncatedPrg { expected, found } => write!(
+                f,
+                "PRG ROM is truncated: expected {} bytes, found {}",
+                expected, found
+            ),
+            RomError::TruncatedChr { expected, found } => write!(
+                f,
+                "CHR ROM is truncated: expected {} bytes, found {}",
+                expected, found
+            ),
+            RomError::MissingPrg => write!(f, "ROM has no PRG ROM"),
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+            RomError::Io(err) => write!(f, "cannot read ROM: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io(err)
+    }
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+}
+
+/// Console the cartridge was made for, from byte 7 of an NES 2.0 header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    /// One of the extended console types listed in byte 13.
+    Extended(u8),
+}
+
+/// CPU/PPU timing the game expects.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    /// Runs on both NTSC and PAL consoles.
+    MultiRegion,
+    Dendy,
+}
+
+/// The input device the game is meant to be played with, from byte 15 of an NES
+/// 2.0 header. Devices this emulator has no use for are kept as their raw number.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayers,
+    VsSystem,
+    Zapper,
+    TwoZappers,
+    PowerPad,
+    ArkanoidVaus,
+    Other(u8),
+}
+
+impl From<u8> for ExpansionDevice {
+    fn from(id: u8) -> Self {
+        match id {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x02 => ExpansionDevice::FourScore,
+            0x03 => ExpansionDevice::FamicomFourPlayers,
+            0x04 | 0x05 => ExpansionDevice::VsSystem,
+            0x07 | 0x08 => ExpansionDevice::Zapper,
+            0x09 => ExpansionDevice::TwoZappers,
+            0x0B | 0x0C => ExpansionDevice::PowerPad,
+            0x0F | 0x10 => ExpansionDevice::ArkanoidVaus,
+            id => ExpansionDevice::Other(id),
+        }
+    }
 }
 
 pub struct Rom {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper: u8,
+    pub mapper: u16,
+    pub submapper: u8,
     pub mirroring: Mirroring,
+    pub battery: bool,
+    pub format: HeaderFormat,
+    /// Volatile PRG RAM in bytes.
+    pub prg_ram_size: usize,
+    /// Battery-backed PRG RAM in bytes.
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub console_type: ConsoleType,
+    pub timing: Timing,
+    pub expansion_device: ExpansionDevice,
 }
 
 impl Rom {
-    pub fn new(raw: &Vec<u8>) -> Result<Rom, String> {
-        if &raw[0..4] != NES_TAG {
-            return Err("Invalid NES file".to_string());
+    /// Parses an iNES or NES 2.0 image. Never panics, whatever `raw` holds.
+    pub fn new(raw: &[u8]) -> Result<Rom, RomError> {
+        if raw.len() >= NES_TAG.len() && raw[0..4] != NES_TAG {
+            return Err(RomError::BadMagic);
         }
-
-        let mapper = (raw[7] & 0xF0) | (raw[6] >> 4);
-        let ines_ver = (raw[7] >> 2) & 0x3;
-
-        if ines_ver != 0 {
-            return Err("Only iNES version 1.0 is supported".to_string());
+        if raw.len() < HEADER_SIZE {
+            return Err(RomError::TooShort { len: raw.len() });
         }
 
+        let format = match (raw[7] >> 2) & 0x3 {
+            0 => HeaderFormat::INes,
+            2 => HeaderFormat::Nes2,
+            _ => return Err(RomError::UnsupportedHeader),
+        };
+
         let four_screen = raw[6] & 0x08 != 0;
         let vertical_mirroring = raw[6] & 0x01 != 0;
         let screen_mirroring = match (four_screen, vertical_mirroring) {
@@ -37,26 +191,153 @@ impl Rom {
             (false, false) => Mirroring::Horizontal,
         };
 
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
-
+        let battery = raw[6] & 0x02 != 0;
         let skip_trainer = raw[6] & 0x04 != 0;
+        let mapper = ((raw[7] & 0xF0) | (raw[6] >> 4)) as u16;
 
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
-
-        println!("PRG ROM size: {}", prg_rom_size);
-        println!("CHR ROM size: {}", chr_rom_size);
-        println!("Mapper: {}", mapper);
-        println!("Mirroring: {:?}", screen_mirroring);
-        println!("PRG Rom start: {}", prg_rom_start);
-        println!("CHR Rom start: {}", chr_rom_start);
-
-        Ok(Rom {
-            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+        let mut rom = Rom {
+            prg_rom: Vec::new(),
+            chr_rom: Vec::new(),
             mapper,
+            submapper: 0,
             mirroring: screen_mirroring,
-        })
+            battery,
+            format,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            console_type: ConsoleType::Nes,
+            timing: Timing::Ntsc,
+            expansion_device: ExpansionDevice::Unspecified,
+        };
+
+        let (prg_rom_size, chr_rom_size) = match format {
+            HeaderFormat::INes => {
+                let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+                if battery {
+                    rom.prg_nvram_size = INES_PRG_RAM_SIZE;
+                } else {
+                    rom.prg_ram_size = INES_PRG_RAM_SIZE;
+                }
+                if chr_rom_size == 0 {
+                    rom.chr_ram_size = INES_CHR_RAM_SIZE;
+                }
+                (raw[4] as usize * PRG_ROM_PAGE_SIZE, chr_rom_size)
+            }
+            HeaderFormat::Nes2 => {
+                rom.parse_nes2_header(raw);
+                (
+                    nes2_rom_size(raw[4], raw[9] & 0x0F, PRG_ROM_PAGE_SIZE),
+                    nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE),
+                )
+            }
+        };
+
+        if prg_rom_size == 0 {
+            return Err(RomError::MissingPrg);
+        }
+
+        let prg_rom_start = HEADER_SIZE + if skip_trainer { TRAINER_SIZE } else { 0 };
+        let rest = raw.get(prg_rom_start..).ok_or(RomError::TruncatedTrainer)?;
+        let prg_rom = rest.get(..prg_rom_size).ok_or(RomError::TruncatedPrg {
+            expected: prg_rom_size,
+            found: rest.len(),
+        })?;
+        let rest = &rest[prg_rom_size..];
+        let chr_rom = rest.get(..chr_rom_size).ok_or(RomError::TruncatedChr {
+            expected: chr_rom_size,
+            found: rest.len(),
+        })?;
+
+        debug!(
+            "{:?} header: mapper {}.{}, {}K PRG ROM, {}K CHR ROM, {:?} mirroring",
+            format,
+            rom.mapper,
+            rom.submapper,
+            prg_rom_size / 1024,
+            chr_rom_size / 1024,
+            screen_mirroring
+        );
+
+        rom.prg_rom = prg_rom.to_vec();
+        rom.chr_rom = chr_rom.to_vec();
+        Ok(rom)
+    }
+
+    /// Reads a whole image from `reader`, such as an open file, and parses it.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Rom, RomError> {
+        let mut raw = Vec::new();
+        reader.read_to_end(&mut raw)?;
+        Rom::new(&raw)
+    }
+
+    /// Bytes 7 to 15 of an NES 2.0 header, apart from the ROM sizes.
+    fn parse_nes2_header(&mut self, raw: &[u8]) {
+        self.mapper |= ((raw[8] & 0x0F) as u16) << 8;
+        self.submapper = raw[8] >> 4;
+
+        self.prg_ram_size = nes2_ram_size(raw[10] & 0x0F);
+        self.prg_nvram_size = nes2_ram_size(raw[10] >> 4);
+        self.chr_ram_size = nes2_ram_size(raw[11] & 0x0F);
+        self.chr_nvram_size = nes2_ram_size(raw[11] >> 4);
+
+        self.console_type = match raw[7] & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(raw[13] & 0x0F),
+        };
+        self.timing = match raw[12] & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        };
+        self.expansion_device = ExpansionDevice::from(raw[15] & 0x3F);
+    }
+}
+
+/// ROM size from the LSB byte and MSB nibble of an NES 2.0 header. An MSB of $F
+/// switches to exponent-multiplier notation: 2^E * (MM * 2 + 1) for an LSB of
+/// `EEEEEEMM`.
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        2usize.saturating_pow(exponent).saturating_mul(multiplier)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * page_size
+    }
+}
+
+/// RAM sizes are shift counts: 0 means none, anything else 64 << shift bytes.
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+impl Snapshot for Mirroring {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(match self {
+            Mirroring::Vertical => 0,
+            Mirroring::Horizontal => 1,
+            Mirroring::FourScreen => 2,
+            Mirroring::SingleScreenLower => 3,
+            Mirroring::SingleScreenUpper => 4,
+        });
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        *self = match state.u8() {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::FourScreen,
+            3 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        };
     }
 }
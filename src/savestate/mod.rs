@@ -0,0 +1,200 @@
+//! Binary snapshots of the whole machine, see `CPU::save_state`.
+//!
+//! A state starts with the magic `NESS` and a little-endian format version,
+//! followed by chunks of a 4-byte tag, a u32 payload length and the payload. Every
+//! subsystem owns one chunk. Loading skips chunks it does not know, and reading
+//! past the end of a payload yields zeros, so fields appended to a chunk in a
+//! later version come up as zero when an older state is loaded. Changes that
+//! cannot be read that way also raise `OLDEST_VERSION`, and older states are
+//! rejected.
+
+pub mod rewind;
+
+use std::collections::HashMap;
+
+pub const MAGIC: [u8; 4] = *b"NESS";
+pub const VERSION: u16 = 3;
+/// Version 2 reorganised the PPU chunk for the dot-based renderer, and version 3
+/// replaced its address and scroll registers with loopy's v/t/x/w.
+pub const OLDEST_VERSION: u16 = 3;
+
+/// State that can be written to and restored from a save-state chunk. Fields are
+/// read back in the order they were written; new fields go at the end.
+pub trait Snapshot {
+    fn save(&self, state: &mut StateWriter);
+    fn load(&mut self, state: &mut StateReader);
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a state with the header already written.
+    pub fn new() -> Self {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        StateWriter { data }
+    }
+
+    /// Writes everything `write` produces as the payload of chunk `tag`.
+    pub fn chunk(&mut self, tag: [u8; 4], write: impl FnOnce(&mut StateWriter)) {
+        self.data.extend_from_slice(&tag);
+        let len_pos = self.data.len();
+        self.data.extend_from_slice(&[0; 4]);
+        write(self);
+        let len = (self.data.len() - len_pos - 4) as u32;
+        self.data[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    /// Length-prefixed block of memory.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0; N];
+        if let Some(data) = self.data.get(self.pos..self.pos + N) {
+            bytes.copy_from_slice(data);
+        }
+        self.pos += N;
+        bytes
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.u8() != 0
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    pub fn usize(&mut self) -> usize {
+        self.u64() as usize
+    }
+
+    /// Fills `bytes` from a block written by `StateWriter::bytes`. A shorter block
+    /// leaves the rest zeroed, a longer one is cut off.
+    pub fn bytes_into(&mut self, bytes: &mut [u8]) {
+        let len = self.u32() as usize;
+        let start = self.pos.min(self.data.len());
+        let end = (self.pos + len).min(self.data.len());
+        let block = &self.data[start..end];
+        let copied = block.len().min(bytes.len());
+        bytes[..copied].copy_from_slice(&block[..copied]);
+        bytes[copied..].fill(0);
+        self.pos += len;
+    }
+}
+
+/// The chunks of a state, by tag.
+pub struct Chunks<'a> {
+    chunks: HashMap<[u8; 4], &'a [u8]>,
+}
+
+impl<'a> Chunks<'a> {
+    /// Checks the header and splits `data` into chunks.
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.get(0..4) != Some(&MAGIC[..]) {
+            return Err("not a save state".to_string());
+        }
+        let version = match data.get(4..6) {
+            Some(version) => u16::from_le_bytes([version[0], version[1]]),
+            None => return Err("save state is truncated".to_string()),
+        };
+        if version > VERSION {
+            return Err(format!(
+                "save state version {} is newer than the supported version {}",
+                version, VERSION
+            ));
+        }
+        if version < OLDEST_VERSION {
+            return Err(format!(
+                "save state version {} is older than the oldest supported version {}",
+                version, OLDEST_VERSION
+            ));
+        }
+
+        let mut chunks = HashMap::new();
+        let mut rest = &data[6..];
+        while !rest.is_empty() {
+            if rest.len() < 8 {
+                return Err("save state is truncated".to_string());
+            }
+            let tag = [rest[0], rest[1], rest[2], rest[3]];
+            let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let payload = rest
+                .get(8..8 + len)
+                .ok_or_else(|| "save state is truncated".to_string())?;
+            chunks.insert(tag, payload);
+            rest = &rest[8 + len..];
+        }
+        Ok(Chunks { chunks })
+    }
+
+    /// Restores `target` from chunk `tag`. Subsystems without a chunk in the state
+    /// keep their current state.
+    pub fn load(&self, tag: [u8; 4], target: &mut dyn Snapshot) {
+        if let Some(payload) = self.chunks.get(&tag) {
+            target.load(&mut StateReader::new(payload));
+        }
+    }
+}
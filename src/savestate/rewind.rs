@@ -0,0 +1,193 @@
+//! A ring buffer of per-frame save states for stepping backward.
+//!
+//! Every `KEYFRAME_INTERVAL` frames a full state is stored as a keyframe, and the
+//! frames in between only store their difference to it: the two states are XORed
+//! and the result run-length encoded, so the RAM, VRAM and registers that did not
+//! change cost almost nothing. Keyframes are run-length encoded as well.
+
+use crate::cpu::CPU;
+use std::collections::VecDeque;
+
+pub const KEYFRAME_INTERVAL: usize = 60;
+
+/// Zero runs shorter than this stay inside a literal, a new run costs 4 bytes.
+const MIN_ZERO_RUN: usize = 4;
+
+/// A keyframe and the deltas recorded against it, oldest first.
+struct Segment {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        1 + self.deltas.len()
+    }
+}
+
+pub struct Rewind {
+    segments: VecDeque<Segment>,
+    /// The decoded keyframe of the newest segment.
+    keyframe: Vec<u8>,
+    capacity: usize,
+    keyframe_interval: usize,
+    len: usize,
+}
+
+impl Rewind {
+    /// Keeps up to `capacity` frames, dropping the oldest ones first.
+    pub fn new(capacity: usize) -> Self {
+        Rewind::with_keyframe_interval(capacity, KEYFRAME_INTERVAL)
+    }
+
+    pub fn with_keyframe_interval(capacity: usize, keyframe_interval: usize) -> Self {
+        let capacity = capacity.max(1);
+        Rewind {
+            segments: VecDeque::new(),
+            keyframe: Vec::new(),
+            capacity,
+            keyframe_interval: keyframe_interval.clamp(1, capacity),
+            len: 0,
+        }
+    }
+
+    /// Records `state` as the newest frame.
+    pub fn push(&mut self, state: &[u8]) {
+        match self.segments.back_mut() {
+            Some(segment) if segment.len() < self.keyframe_interval => {
+                segment.deltas.push(encode(state, &self.keyframe));
+            }
+            _ => {
+                self.segments.push_back(Segment {
+                    keyframe: encode(state, &[]),
+                    deltas: Vec::new(),
+                });
+                self.keyframe = state.to_vec();
+            }
+        }
+        self.len += 1;
+
+        while self.len > self.capacity {
+            let oldest = self.segments.pop_front().unwrap();
+            self.len -= oldest.len();
+        }
+    }
+
+    /// Removes and returns the newest frame.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let segment = self.segments.back_mut()?;
+        self.len -= 1;
+        if let Some(delta) = segment.deltas.pop() {
+            return Some(decode(&delta, &self.keyframe));
+        }
+
+        self.segments.pop_back();
+        let keyframe = match self.segments.back() {
+            Some(segment) => decode(&segment.keyframe, &[]),
+            None => Vec::new(),
+        };
+        Some(std::mem::replace(&mut self.keyframe, keyframe))
+    }
+
+    /// Captures the whole machine as the newest frame.
+    pub fn capture(&mut self, cpu: &CPU) {
+        self.push(&cpu.save_state());
+    }
+
+    /// Restores the newest frame and drops it, returning false once the buffer is
+    /// empty.
+    pub fn step_back(&mut self, cpu: &mut CPU) -> bool {
+        match self.pop() {
+            Some(state) => cpu
+                .load_state(&state)
+                .expect("rewind buffer holds a malformed state"),
+            None => return false,
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.keyframe.clear();
+        self.len = 0;
+    }
+
+    /// Bytes taken by the encoded frames.
+    pub fn memory_usage(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| {
+                segment.keyframe.len() + segment.deltas.iter().map(Vec::len).sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+/// Encodes `state` XORed with `base` (zero past its end) as its u32 length followed
+/// by runs of a u16 count of zero bytes, a u16 literal length and the literal bytes.
+fn encode(state: &[u8], base: &[u8]) -> Vec<u8> {
+    let diff: Vec<u8> = state
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ base.get(i).copied().unwrap_or(0))
+        .collect();
+    let zero_run_at = |pos: usize| {
+        diff[pos..]
+            .iter()
+            .take(MIN_ZERO_RUN)
+            .filter(|byte| **byte == 0)
+            .count()
+            == MIN_ZERO_RUN.min(diff.len() - pos)
+    };
+
+    let mut out = (diff.len() as u32).to_le_bytes().to_vec();
+    let mut pos = 0;
+    while pos < diff.len() {
+        let zeros = diff[pos..]
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|byte| **byte == 0)
+            .count();
+        pos += zeros;
+
+        let start = pos;
+        while pos < diff.len() && pos - start < u16::MAX as usize && !zero_run_at(pos) {
+            pos += 1;
+        }
+        out.extend_from_slice(&(zeros as u16).to_le_bytes());
+        out.extend_from_slice(&((pos - start) as u16).to_le_bytes());
+        out.extend_from_slice(&diff[start..pos]);
+    }
+    out
+}
+
+fn decode(data: &[u8], base: &[u8]) -> Vec<u8> {
+    let word = |pos: usize| u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+    let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let mut state: Vec<u8> = (0..len)
+        .map(|i| base.get(i).copied().unwrap_or(0))
+        .collect();
+
+    let mut pos = 4;
+    let mut at = 0;
+    while pos < data.len() {
+        let zeros = word(pos);
+        let literal = word(pos + 2);
+        pos += 4;
+        at += zeros;
+        for (byte, diff) in state[at..at + literal].iter_mut().zip(&data[pos..]) {
+            *byte ^= diff;
+        }
+        at += literal;
+        pos += literal;
+    }
+    state
+}
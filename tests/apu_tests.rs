@@ -0,0 +1,100 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::apu::{SequencerMode, APU};
+
+    fn tick(apu: &mut APU, cycles: usize) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_status_reports_length_counters() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0101);
+        apu.write_register(0x4003, 0b0000_1000); // pulse 1, length index 1
+        apu.write_register(0x400B, 0b0000_1000); // triangle, length index 1
+        apu.write_register(0x400F, 0b0000_1000); // noise is disabled, not loaded
+
+        assert_eq!(apu.read_status() & 0b1_1111, 0b0000_0101);
+
+        apu.write_register(0x4015, 0b0000_0001);
+        assert_eq!(apu.read_status() & 0b1_1111, 0b0000_0001);
+    }
+
+    #[test]
+    fn test_length_counter_is_clocked_by_half_frames() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4003, 0b0001_1000); // length index 3 -> 2
+
+        tick(&mut apu, 14913);
+        assert_eq!(apu.read_status() & 1, 1);
+
+        tick(&mut apu, 29829 - 14913);
+        assert_eq!(apu.read_status() & 1, 0);
+    }
+
+    #[test]
+    fn test_length_counter_halt() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b0010_0000);
+        apu.write_register(0x4003, 0b0001_1000);
+
+        tick(&mut apu, 29830 * 2);
+        assert_eq!(apu.read_status() & 1, 1);
+    }
+
+    #[test]
+    fn test_frame_irq_in_four_step_mode() {
+        let mut apu = APU::new();
+
+        tick(&mut apu, 29827);
+        assert!(!apu.irq_pending());
+
+        tick(&mut apu, 1);
+        assert!(apu.irq_pending());
+
+        let status = apu.read_status();
+        assert_eq!(status & 0b0100_0000, 0b0100_0000);
+        assert_eq!(apu.read_status() & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn test_frame_irq_inhibit() {
+        let mut apu = APU::new();
+        apu.write_register(0x4017, 0b0100_0000);
+
+        tick(&mut apu, 29830 * 2);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn test_five_step_mode_has_no_irq() {
+        let mut apu = APU::new();
+        apu.write_register(0x4017, 0b1000_0000);
+        assert_eq!(apu.sequencer_mode(), SequencerMode::FiveStep);
+
+        tick(&mut apu, 37282 * 2);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn test_dmc_fetches_and_raises_irq() {
+        let mut apu = APU::new();
+        apu.write_register(0x4010, 0b1000_1111);
+        apu.write_register(0x4012, 0x00); // $C000
+        apu.write_register(0x4013, 0x00); // 1 byte
+        apu.write_register(0x4015, 0b0001_0000);
+
+        assert_eq!(apu.read_status() & 0b0001_0000, 0b0001_0000);
+        assert_eq!(apu.dmc_fetch_request(), Some(0xC000));
+
+        apu.fill_dmc_sample(0xFF);
+        assert_eq!(apu.dmc_fetch_request(), None);
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status() & 0b1001_0000, 0b1000_0000);
+    }
+}
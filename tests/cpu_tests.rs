@@ -1,16 +1,84 @@
 #[cfg(test)]
 
 mod tests {
-    use nes::bus::Bus;
+    use nes::bus::mapper::Mapper;
+    use nes::bus::{Bus, IrqSource};
+    use nes::cpu::trace::{trace, Tracer};
     use nes::cpu::StatusFlags;
     use nes::cpu::CPU;
-    use nes::rom::Rom;
+    use nes::rom::{Mirroring, Rom};
+    use nes::savestate::{Snapshot, StateReader, StateWriter};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    // 32K of writable PRG with an IRQ line the test can pull
+    struct TestCart {
+        prg: Vec<u8>,
+        irq: bool,
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl Snapshot for TestCart {
+        fn save(&self, state: &mut StateWriter) {
+            state.bytes(&self.prg);
+            state.bool(self.irq);
+        }
+
+        fn load(&mut self, state: &mut StateReader) {
+            state.bytes_into(&mut self.prg);
+            self.irq = state.bool();
+        }
+    }
+
+    impl Mapper for TestCart {
+        fn irq_pending(&self) -> bool {
+            self.irq
+        }
+        fn read_prg_byte(&self, addr: u16) -> u8 {
+            if addr >= 0x8000 {
+                self.prg[(addr - 0x8000) as usize]
+            } else {
+                0
+            }
+        }
+        fn write_prg_byte(&mut self, addr: u16, data: u8) {
+            self.writes.push((addr, data));
+            if addr >= 0x8000 {
+                self.prg[(addr - 0x8000) as usize] = data;
+            }
+        }
+        fn read_chr_byte(&self, _addr: u16) -> u8 {
+            0
+        }
+        fn write_chr_byte(&mut self, _addr: u16, _data: u8) {}
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+    }
+
+    // program at $8000, NMI handler at $9000, IRQ/BRK handler at $A000
+    fn test_cpu(program: &[u8]) -> (CPU<'static>, Rc<RefCell<TestCart>>) {
+        let mut prg = vec![0xEA; 0x8000];
+        prg[..program.len()].copy_from_slice(program);
+        prg[0x7FFA..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0xA0]);
+
+        let cart = Rc::new(RefCell::new(TestCart {
+            prg,
+            irq: false,
+            writes: Vec::new(),
+        }));
+        let bus = Bus::with_mapper(cart.clone(), |_, _| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        (cpu, cart)
+    }
 
     #[test]
     fn test_adc_immediate_mode() {
         let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
         let rom = Rom::new(&bytes).unwrap();
-        let bus = Bus::new(rom, |_| {});
+        let bus = Bus::new(rom, |_, _| {}).unwrap();
         let mut cpu = CPU::new(bus);
 
         cpu.a = 0x10;
@@ -33,7 +101,7 @@ mod tests {
     fn test_lda_from_memory() {
         let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
         let rom = Rom::new(&bytes).unwrap();
-        let bus = Bus::new(rom, |_| {});
+        let bus = Bus::new(rom, |_, _| {}).unwrap();
         let mut cpu = CPU::new(bus);
 
         cpu.a = 0x10;
@@ -49,4 +117,273 @@ mod tests {
 
         assert_eq!(cpu.a, 0x11);
     }
+
+    #[test]
+    fn test_irq_is_masked_by_i_flag() {
+        let (mut cpu, cart) = test_cpu(&[0xEA, 0xEA, 0xEA]);
+        cart.borrow_mut().irq = true;
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x8002);
+    }
+
+    #[test]
+    fn test_irq_after_cli_is_delayed_one_instruction() {
+        // CLI; NOP; NOP
+        let (mut cpu, cart) = test_cpu(&[0x58, 0xEA, 0xEA]);
+        cart.borrow_mut().irq = true;
+
+        cpu.step(); // CLI
+        cpu.step(); // NOP still runs before the IRQ is taken
+        assert_eq!(cpu.pc, 0x8002);
+
+        cpu.step(); // IRQ sequence, then the first handler instruction
+        assert_eq!(cpu.pc, 0xA001);
+        assert!(cpu.status.contains(StatusFlags::INTERRUPT));
+
+        // return address $8002 and status with B clear, unused set
+        assert_eq!(cpu.bus.mem_read(0x01FD), 0x80);
+        assert_eq!(cpu.bus.mem_read(0x01FC), 0x02);
+        assert_eq!(cpu.bus.mem_read(0x01FB) & 0b0011_0000, 0b0010_0000);
+    }
+
+    #[test]
+    fn test_brk_pushes_break_flag() {
+        let (mut cpu, _) = test_cpu(&[0x00, 0xFF]);
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0xA000);
+        assert_eq!(cpu.bus.mem_read(0x01FC), 0x02);
+        assert_eq!(cpu.bus.mem_read(0x01FB) & 0b0011_0000, 0b0011_0000);
+    }
+
+    #[test]
+    fn test_irq_line_sources() {
+        let (mut cpu, cart) = test_cpu(&[]);
+        assert!(cpu.bus.irq_sources().is_empty());
+
+        cart.borrow_mut().irq = true;
+        assert_eq!(cpu.bus.irq_sources(), IrqSource::MAPPER);
+
+        for _ in 0..29830 {
+            cpu.bus.tick(1);
+        }
+        assert_eq!(
+            cpu.bus.irq_sources(),
+            IrqSource::MAPPER | IrqSource::APU_FRAME
+        );
+    }
+
+    #[test]
+    fn test_sbc_sets_overflow_and_borrow() {
+        // SEC; LDA #$80; SBC #$01
+        let (mut cpu, _) = test_cpu(&[0x38, 0xA9, 0x80, 0xE9, 0x01]);
+        cpu.step();
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.a, 0x7F);
+        assert!(cpu.status.contains(StatusFlags::CARRY));
+        assert!(cpu.status.contains(StatusFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_lax_and_sax() {
+        // LAX $10; SAX $11 with X changed in between
+        let (mut cpu, _) = test_cpu(&[0xA7, 0x10, 0xE8, 0x87, 0x11]);
+        cpu.bus.mem_write(0x10, 0xF0);
+
+        cpu.step();
+        assert_eq!((cpu.a, cpu.x), (0xF0, 0xF0));
+        assert!(cpu.status.contains(StatusFlags::NEGATIVE));
+
+        cpu.step(); // INX
+        cpu.step();
+        assert_eq!(cpu.bus.mem_read(0x11), 0xF0 & 0xF1);
+    }
+
+    #[test]
+    fn test_read_modify_write_combos() {
+        // LDA #$10; DCP $20; ISB $21; SLO $22; SRE $23
+        let (mut cpu, _) = test_cpu(&[0xA9, 0x10, 0xC7, 0x20, 0xE7, 0x21, 0x07, 0x22, 0x47, 0x23]);
+        cpu.bus.mem_write(0x20, 0x11);
+        cpu.bus.mem_write(0x21, 0x04);
+        cpu.bus.mem_write(0x22, 0x81);
+        cpu.bus.mem_write(0x23, 0x03);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.mem_read(0x20), 0x10);
+        assert!(cpu.status.contains(StatusFlags::ZERO | StatusFlags::CARRY));
+
+        cpu.step();
+        assert_eq!(cpu.bus.mem_read(0x21), 0x05);
+        assert_eq!(cpu.a, 0x0B);
+
+        cpu.step();
+        assert_eq!(cpu.bus.mem_read(0x22), 0x02);
+        assert_eq!(cpu.a, 0x0B);
+        assert!(cpu.status.contains(StatusFlags::CARRY));
+
+        cpu.step();
+        assert_eq!(cpu.bus.mem_read(0x23), 0x01);
+        assert_eq!(cpu.a, 0x0A);
+        assert!(cpu.status.contains(StatusFlags::CARRY));
+    }
+
+    #[test]
+    fn test_immediate_combos() {
+        // LDA #$FF; ARR #$C0; LDX #$0F; AXS #$02
+        let (mut cpu, _) = test_cpu(&[0xA9, 0xFF, 0x6B, 0xC0, 0xA2, 0x0F, 0xCB, 0x02]);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.a, 0x60);
+        assert!(cpu.status.contains(StatusFlags::CARRY));
+        assert!(!cpu.status.contains(StatusFlags::OVERFLOW));
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.x, 0xFE);
+        assert!(!cpu.status.contains(StatusFlags::CARRY));
+    }
+
+    #[test]
+    fn test_multi_byte_nop_and_kil() {
+        // NOP $1234,X; NOP $10; KIL
+        let (mut cpu, _) = test_cpu(&[0x1C, 0x34, 0x12, 0x04, 0x10, 0x02]);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x8003);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x8005);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x8005);
+    }
+
+    fn cycles_for(cpu: &mut CPU, steps: usize) -> usize {
+        let start = cpu.bus.cycles();
+        for _ in 0..steps {
+            cpu.step();
+        }
+        cpu.bus.cycles() - start
+    }
+
+    #[test]
+    fn test_indexed_page_cross_costs_a_cycle() {
+        // LDX #$01; LDA $80FE,X; LDA $80FF,X; STA $6000,X
+        let (mut cpu, _) = test_cpu(&[
+            0xA2, 0x01, 0xBD, 0xFE, 0x80, 0xBD, 0xFF, 0x80, 0x9D, 0x00, 0x60,
+        ]);
+        assert_eq!(cycles_for(&mut cpu, 1), 2);
+        assert_eq!(cycles_for(&mut cpu, 1), 4);
+        assert_eq!(cycles_for(&mut cpu, 1), 5);
+        // stores always take the fix-up cycle
+        assert_eq!(cycles_for(&mut cpu, 1), 5);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_twice() {
+        // INC $6000
+        let (mut cpu, cart) = test_cpu(&[0xEE, 0x00, 0x60]);
+        assert_eq!(cycles_for(&mut cpu, 1), 6);
+        assert_eq!(cart.borrow().writes, vec![(0x6000, 0x00), (0x6000, 0x01)]);
+    }
+
+    #[test]
+    fn test_control_flow_cycles() {
+        // JSR $8010; ...; $8010: RTS
+        let mut program = vec![0x20, 0x10, 0x80, 0xEA];
+        program.resize(0x10, 0xEA);
+        // $8010: RTS
+        program.push(0x60);
+        let (mut cpu, _) = test_cpu(&program);
+
+        assert_eq!(cycles_for(&mut cpu, 1), 6);
+        assert_eq!(cpu.pc, 0x8010);
+        assert_eq!(cycles_for(&mut cpu, 1), 6);
+        assert_eq!(cpu.pc, 0x8003);
+
+        // BEQ not taken, CLC, BCC taken on the same page
+        let (mut cpu, _) = test_cpu(&[0xF0, 0x10, 0x18, 0x90, 0x02]);
+        assert_eq!(cycles_for(&mut cpu, 1), 2);
+        assert_eq!(cycles_for(&mut cpu, 2), 2 + 3);
+        assert_eq!(cpu.pc, 0x8007);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_the_cpu() {
+        // LDA #$02; STA $4014
+        let (mut cpu, _) = test_cpu(&[0xA9, 0x02, 0x8D, 0x14, 0x40]);
+        cpu.step();
+
+        let cycles = cycles_for(&mut cpu, 1);
+        assert!(cycles == 4 + 513 || cycles == 4 + 514);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let (mut cpu, _) = test_cpu(&[0x00, 0xFF]);
+        cpu.bus.ppu.registers.nmi_interrupt = Some(1);
+        cpu.step();
+
+        // the NMI vector is taken, but the pushed status still has B set
+        assert_eq!(cpu.pc, 0x9000);
+        assert_eq!(cpu.bus.mem_read(0x01FB) & 0b0011_0000, 0b0011_0000);
+        assert!(!cpu.bus.ppu.nmi_pending());
+    }
+
+    #[test]
+    fn test_trace_uses_nestest_format() {
+        // LDX #$02; LDA $01F0,X; JMP ($02FF)
+        let (mut cpu, _) = test_cpu(&[0xA2, 0x02, 0xBD, 0xF0, 0x01, 0x6C, 0xFF, 0x02]);
+        cpu.bus.mem_write(0x01F2, 0x5A);
+        cpu.bus.mem_write(0x02FF, 0x34);
+        cpu.bus.mem_write(0x0200, 0x12);
+
+        assert_eq!(
+            trace(&cpu),
+            "8000  A2 02     LDX #$02                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+        cpu.step();
+        assert_eq!(
+            trace(&cpu),
+            "8002  BD F0 01  LDA $01F0,X @ 01F2 = 5A         A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9"
+        );
+        cpu.step();
+        assert_eq!(
+            trace(&cpu),
+            "8005  6C FF 02  JMP ($02FF) = 1234              A:5A X:02 Y:00 P:24 SP:FD PPU:  0, 39 CYC:13"
+        );
+    }
+
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tracer_filters_by_pc_range() {
+        let (mut cpu, _) = test_cpu(&[0xEA, 0xEA, 0xEA, 0xEA]);
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut tracer = Tracer::new(SharedBuffer(buffer.clone()));
+        tracer.set_pc_range(0x8001..=0x8002);
+        cpu.set_tracer(tracer);
+
+        cpu.step();
+        cpu.step();
+        cpu.tracer_mut().unwrap().set_enabled(false);
+        cpu.step();
+
+        let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let pcs: Vec<&str> = log.lines().map(|line| &line[..4]).collect();
+        assert_eq!(pcs, vec!["8001"]);
+    }
 }
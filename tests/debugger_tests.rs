@@ -0,0 +1,205 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::mapper::Mapper0;
+    use nes::bus::Bus;
+    use nes::cpu::CPU;
+    use nes::debugger::{
+        repl, Access, AccessKind, AccessKinds, AddressSpace, Comparison, Condition, Debugger,
+        Register, StopReason,
+    };
+    use nes::rom::Mirroring;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    // enables the vblank NMI, then counts up in X through a subroutine:
+    //
+    // 8000  LDA #$80      8007  JSR $8020    8020  LDA #$05
+    // 8002  STA PPUCTRL   800A  INX          8022  RTS
+    // 8005  LDX #$00      800B  STX $0300
+    //                     800E  JMP $8007    9000  RTI (NMI)
+    const PROGRAM: [u8; 17] = [
+        0xA9, 0x80, 0x8D, 0x00, 0x20, 0xA2, 0x00, 0x20, 0x20, 0x80, 0xE8, 0x8E, 0x00, 0x03, 0x4C,
+        0x07, 0x80,
+    ];
+
+    fn test_cpu() -> CPU<'static> {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..PROGRAM.len()].copy_from_slice(&PROGRAM);
+        prg_rom[0x20..0x23].copy_from_slice(&[0xA9, 0x05, 0x60]);
+        prg_rom[0x1000] = 0x40;
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0x90]);
+
+        let mapper = Mapper0::new(prg_rom, vec![0; 0x2000], Mirroring::Horizontal);
+        let bus = Bus::with_mapper(Rc::new(RefCell::new(mapper)), |_, _| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_breakpoint_stops_before_instruction_and_resumes() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        let id = debugger.add_breakpoint(0x800A, None);
+
+        assert_eq!(debugger.run(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!(cpu.pc, 0x800A);
+        assert_eq!(cpu.x, 0);
+
+        // continuing leaves the breakpoint and stops on its next hit
+        assert_eq!(debugger.run(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!(cpu.x, 1);
+
+        assert!(debugger.remove(id));
+        assert!(!debugger.remove(id));
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        let condition = Condition {
+            register: Register::X,
+            comparison: Comparison::Eq,
+            value: 3,
+        };
+        let id = debugger.add_breakpoint(0x800B, Some(condition));
+
+        assert_eq!(debugger.run(&mut cpu), StopReason::Breakpoint(id));
+        assert_eq!(cpu.x, 3);
+        assert_eq!(cpu.bus.peek(0x0300), 2);
+    }
+
+    #[test]
+    fn test_watchpoints_on_cpu_and_ppu_space() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        let ctrl = debugger.add_watchpoint(AddressSpace::Cpu, 0x2000..=0x2007, AccessKinds::WRITE);
+        let ram = debugger.add_watchpoint(AddressSpace::Cpu, 0x0300..=0x0300, AccessKinds::WRITE);
+
+        let stop = debugger.run(&mut cpu);
+        assert_eq!(
+            stop,
+            StopReason::Watchpoint {
+                id: ctrl,
+                access: Access {
+                    space: AddressSpace::Cpu,
+                    kind: AccessKind::Write,
+                    addr: 0x2000,
+                    data: 0x80,
+                },
+            }
+        );
+        assert_eq!(cpu.pc, 0x8005);
+
+        assert!(matches!(
+            debugger.run(&mut cpu),
+            StopReason::Watchpoint { id, .. } if id == ram
+        ));
+        assert_eq!(cpu.pc, 0x800E);
+
+        // PPUDATA writes land in the PPU address space at the VRAM address
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        let vram = debugger.add_watchpoint(AddressSpace::Ppu, 0x2300..=0x23FF, AccessKinds::WRITE);
+        cpu.bus.ppu.registers.write_to_ppu_addr(0x23);
+        cpu.bus.ppu.registers.write_to_ppu_addr(0x05);
+        cpu.bus.mem_write(0x2007, 0x66);
+        assert_eq!(debugger.step_into(&mut cpu), StopReason::Stepped);
+
+        cpu.a = 0x77;
+        cpu.bus.ram[0x10..0x13].copy_from_slice(&[0x8D, 0x07, 0x20]); // STA PPUDATA
+        cpu.pc = 0x0010;
+        let stop = debugger.step_into(&mut cpu);
+        assert_eq!(
+            stop,
+            StopReason::Watchpoint {
+                id: vram,
+                access: Access {
+                    space: AddressSpace::Ppu,
+                    kind: AccessKind::Write,
+                    addr: 0x2306,
+                    data: 0x77,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_accesses_are_not_recorded_after_the_debugger_stops() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(AddressSpace::Cpu, 0x0300..=0x0300, AccessKinds::WRITE);
+        assert!(matches!(
+            debugger.run(&mut cpu),
+            StopReason::Watchpoint { .. }
+        ));
+
+        for _ in 0..100 {
+            cpu.step();
+        }
+        assert_eq!(cpu.drain_accesses().count(), 0);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        for _ in 0..3 {
+            debugger.step_into(&mut cpu);
+        }
+        assert_eq!(cpu.pc, 0x8007);
+
+        assert_eq!(debugger.step_over(&mut cpu), StopReason::Stepped);
+        assert_eq!((cpu.pc, cpu.a), (0x800A, 0x05));
+
+        debugger.step_into(&mut cpu);
+        debugger.step_into(&mut cpu);
+        debugger.step_into(&mut cpu);
+        assert_eq!(debugger.step_into(&mut cpu), StopReason::Stepped);
+        assert_eq!(cpu.pc, 0x8020);
+
+        assert_eq!(debugger.step_out(&mut cpu), StopReason::Stepped);
+        assert_eq!(cpu.pc, 0x800A);
+    }
+
+    #[test]
+    fn test_run_to_scanline_and_nmi() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+
+        assert_eq!(
+            debugger.run_to_scanline(&mut cpu, 100),
+            StopReason::Scanline(100)
+        );
+        assert_eq!(cpu.bus.ppu.scanline(), 100);
+
+        assert_eq!(debugger.run_to_nmi(&mut cpu), StopReason::Nmi);
+        assert_eq!(cpu.pc, 0x9000);
+        assert_eq!(cpu.bus.ppu.scanline(), 241);
+
+        assert_eq!(debugger.step_out(&mut cpu), StopReason::Stepped);
+        assert!((0x8007..0x8011).contains(&cpu.pc));
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let mut cpu = test_cpu();
+        let mut debugger = Debugger::new();
+        let input = "break 800B if X==2\ncontinue\nlist\nmem 0300 4\ndis 8007 2\nbogus\nquit\n";
+        let mut out = Vec::new();
+
+        repl::run(&mut debugger, &mut cpu, Cursor::new(input), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("breakpoint #1 at $800B"));
+        assert!(out.contains("(nes) breakpoint #1\n800B  8E 00 03  STX $0300 = 01"));
+        assert!(out.contains("#1  break $800B if X == $2"));
+        assert!(out.contains("0300  01 00 00 00"));
+        assert!(out.contains("   8007  20 20 80  JSR $8020"));
+        assert!(out.contains("error: unknown command 'bogus'"));
+        assert_eq!(cpu.x, 2);
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::mapper::Mapper0;
+    use nes::bus::Bus;
+    use nes::disasm::{self, Operand};
+    use nes::rom::Mirroring;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // program at $8000 of a 16K NROM cartridge
+    fn test_bus(program: &[u8]) -> Bus<'static> {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        let mapper = Mapper0::new(prg_rom, vec![0; 0x2000], Mirroring::Horizontal);
+        Bus::with_mapper(Rc::new(RefCell::new(mapper)), |_, _| {})
+    }
+
+    #[test]
+    fn test_decode_addressing_modes() {
+        let program = [
+            0xA9, 0x10, // LDA #$10
+            0xB5, 0x20, // LDA $20,X
+            0xB6, 0x20, // LDX $20,Y
+            0xBD, 0x34, 0x12, // LDA $1234,X
+            0xA1, 0x40, // LDA ($40,X)
+            0xB1, 0x40, // LDA ($40),Y
+            0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+            0x0A, // ASL A
+            0xE8, // INX
+            0xA7, 0x30, // *LAX $30
+        ];
+        let listing = disasm::disassemble(&test_bus(&program), 0x8000..=0x8013);
+        let text: Vec<String> = listing
+            .instructions
+            .iter()
+            .map(|ins| ins.to_string())
+            .collect();
+
+        assert_eq!(
+            text,
+            vec![
+                "8000  A9 10     LDA #$10",
+                "8002  B5 20     LDA $20,X",
+                "8004  B6 20     LDX $20,Y",
+                "8006  BD 34 12  LDA $1234,X",
+                "8009  A1 40     LDA ($40,X)",
+                "800B  B1 40     LDA ($40),Y",
+                "800D  6C FC FF  JMP ($FFFC)",
+                "8010  0A        ASL A",
+                "8011  E8        INX",
+                "8012  A7 30     *LAX $30",
+            ]
+        );
+        assert_eq!(listing.instructions[6].operand, Operand::Indirect(0xFFFC));
+        assert_eq!(listing.instructions[6].target(), None);
+    }
+
+    #[test]
+    fn test_labels_for_branch_and_jump_targets() {
+        let program = [
+            0x20, 0x08, 0x80, // JSR L_8008
+            0xAD, 0x02, 0x20, // LDA PPUSTATUS
+            0x10, 0xFB, // BPL L_8003
+            0x8D, 0x14, 0x40, // STA OAMDMA
+            0x4C, 0x00, 0x90, // JMP $9000, outside the listing
+        ];
+        let listing = disasm::disassemble(&test_bus(&program), 0x8000..=0x800D);
+
+        assert_eq!(listing.instructions[2].target(), Some(0x8003));
+        assert_eq!(
+            listing.labels.keys().copied().collect::<Vec<u16>>(),
+            vec![0x8003, 0x8008]
+        );
+        assert_eq!(
+            listing.to_string(),
+            "8000  20 08 80  JSR L_8008\n\
+             L_8003:\n\
+             8003  AD 02 20  LDA PPUSTATUS\n\
+             8006  10 FB     BPL L_8003\n\
+             L_8008:\n\
+             8008  8D 14 40  STA OAMDMA\n\
+             800B  4C 00 90  JMP $9000\n"
+        );
+    }
+
+    #[test]
+    fn test_disassembling_registers_has_no_side_effects() {
+        let mut bus = test_bus(&[]);
+        bus.ppu.registers.status.set_vblank_status(true);
+
+        let listing = disasm::disassemble(&bus, 0x2000..=0x2007);
+
+        assert!(!listing.instructions.is_empty());
+        assert!(bus.ppu.registers.status.is_in_vblank());
+        assert_eq!(bus.mem_read(0x2002) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn test_disassembly_stops_at_end_of_address_space() {
+        let listing = disasm::disassemble_with(|_| 0xAD, 0xFFFD..=0xFFFF);
+
+        assert_eq!(listing.instructions.len(), 1);
+        assert_eq!(listing.instructions[0].bytes, vec![0xAD, 0xAD, 0xAD]);
+        assert_eq!(listing.instructions[0].next_addr(), 0x0000);
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::Bus;
+    use nes::joypad::{InputDevice, Joypad, JoypadButton};
+    use nes::rom::Rom;
+
+    #[test]
+    fn test_strobe_mode_reports_button_a() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        for _ in 0..10 {
+            assert_eq!(joypad.read(), 1);
+        }
+    }
+
+    #[test]
+    fn test_shift_register_order() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(JoypadButton::BUTTON_B | JoypadButton::START | JoypadButton::RIGHT);
+        joypad.write(1);
+        joypad.write(0);
+
+        let bits: Vec<u8> = (0..8).map(|_| joypad.read()).collect();
+        assert_eq!(bits, vec![0, 1, 0, 1, 0, 0, 0, 1]);
+
+        // official controllers return 1 after the 8th read
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+
+        joypad.write(1);
+        joypad.write(0);
+        assert_eq!(joypad.read(), 0);
+        assert_eq!(joypad.read(), 1);
+    }
+
+    #[test]
+    fn test_bus_reads_both_ports_with_open_bus_bits() {
+        let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+        let rom = Rom::new(&bytes).unwrap();
+        let mut bus = Bus::new(rom, |_, _| {}).unwrap();
+
+        bus.joypads[0].set_buttons(JoypadButton::BUTTON_A);
+        bus.joypads[1].set_buttons(JoypadButton::BUTTON_B);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+
+        // leave $40 on the data bus, like the operand high byte of `LDA $4016`
+        bus.ram[0x10] = 0x40;
+        bus.mem_read(0x0010);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+        assert_eq!(bus.mem_read(0x4017), 0x41);
+    }
+
+    #[test]
+    fn test_bus_peek_does_not_shift_or_acknowledge() {
+        let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+        let rom = Rom::new(&bytes).unwrap();
+        let mut bus = Bus::new(rom, |_, _| {}).unwrap();
+
+        bus.joypads[0].set_buttons(JoypadButton::BUTTON_A);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        bus.ppu.registers.status.set_vblank_status(true);
+
+        for _ in 0..3 {
+            assert_eq!(bus.peek(0x4016) & 1, 1);
+            assert_eq!(bus.peek(0x2002) >> 7, 1);
+            assert_eq!(bus.peek(0x200A) >> 7, 1); // register mirror
+        }
+        assert_eq!(bus.peek(0x8000), bus.mem_read(0x8000));
+
+        assert_eq!(bus.mem_read(0x4016) & 1, 1);
+        assert_eq!(bus.mem_read(0x4016) & 1, 0);
+        assert_eq!(bus.mem_read(0x2002) >> 7, 1);
+        assert_eq!(bus.peek(0x2002) >> 7, 0);
+    }
+}
@@ -0,0 +1,370 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::mapper::{self, Mapper, Mapper0, Mapper1, Mapper2, Mapper4};
+    use nes::bus::Bus;
+    use nes::rom::{Mirroring, Rom};
+    use nes::savestate::{Snapshot, StateReader, StateWriter};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn test_mapper0() -> Mapper0 {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0] = 0x11;
+        prg_rom[0x3FFF] = 0x22;
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[0x1234] = 0x33;
+        Mapper0::new(prg_rom, chr_rom, Mirroring::Vertical)
+    }
+
+    #[test]
+    fn test_mapper0_mirrors_16k_prg_rom() {
+        let mapper = test_mapper0();
+        assert_eq!(mapper.read_prg_byte(0x8000), 0x11);
+        assert_eq!(mapper.read_prg_byte(0xC000), 0x11);
+        assert_eq!(mapper.read_prg_byte(0xBFFF), 0x22);
+        assert_eq!(mapper.read_prg_byte(0xFFFF), 0x22);
+    }
+
+    #[test]
+    fn test_bus_and_ppu_share_the_cartridge() {
+        let mut bus = Bus::with_mapper(Rc::new(RefCell::new(test_mapper0())), |_, _| {});
+
+        assert_eq!(bus.mem_read(0x8000), 0x11);
+        bus.mem_write(0x8000, 0xFF); // ROM writes are ignored instead of panicking
+        assert_eq!(bus.mem_read(0x8000), 0x11);
+
+        bus.mem_write(0x6000, 0x44);
+        assert_eq!(bus.mem_read(0x6000), 0x44);
+
+        assert_eq!(bus.ppu.read_chr(0x1234), 0x33);
+        assert_eq!(bus.ppu.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mapper_is_selected_from_rom() {
+        let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+        let mut rom = Rom::new(&bytes).unwrap();
+        rom.mapper = 0xFF;
+        assert!(mapper::from_rom(rom).is_err());
+    }
+
+    // writes `data` to the PPU through $2006/$2007
+    fn ppu_write(bus: &mut Bus, addr: u16, data: &[u8]) {
+        bus.mem_write(0x2006, (addr >> 8) as u8);
+        bus.mem_write(0x2006, addr as u8);
+        for byte in data {
+            bus.mem_write(0x2007, *byte);
+        }
+    }
+
+    #[test]
+    fn test_chr_ram_is_writable_through_ppudata() {
+        let mapper = Mapper0::new(vec![0; 0x4000], Vec::<u8>::new(), Mirroring::Horizontal);
+        let mut bus = Bus::with_mapper(Rc::new(RefCell::new(mapper)), |_, _| {});
+
+        ppu_write(&mut bus, 0x1FF0, &[0xAA, 0xBB]);
+        assert_eq!(bus.ppu.read_chr(0x1FF0), 0xAA);
+        assert_eq!(bus.ppu.read_chr(0x1FF1), 0xBB);
+
+        // reads through $2007 are buffered by one
+        ppu_write(&mut bus, 0x1FF0, &[]);
+        bus.mem_read(0x2007);
+        assert_eq!(bus.mem_read(0x2007), 0xAA);
+
+        // CHR ROM stays read-only
+        let mut bus = Bus::with_mapper(Rc::new(RefCell::new(test_mapper0())), |_, _| {});
+        ppu_write(&mut bus, 0x1234, &[0x99]);
+        assert_eq!(bus.ppu.read_chr(0x1234), 0x33);
+    }
+
+    #[test]
+    fn test_uxrom_banking_with_chr_ram() {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let mut mapper = Mapper2::new(prg_rom, Vec::<u8>::new(), Mirroring::Vertical);
+
+        assert_eq!(mapper.read_prg_byte(0x8000), 0);
+        assert_eq!(mapper.read_prg_byte(0xFFFF), 7);
+        mapper.write_prg_byte(0x8000, 5);
+        assert_eq!(mapper.read_prg_byte(0xBFFF), 5);
+        assert_eq!(mapper.read_prg_byte(0xC000), 7);
+
+        mapper.write_chr_byte(0x0123, 0x42);
+        assert_eq!(mapper.read_chr_byte(0x0123), 0x42);
+    }
+
+    #[test]
+    fn test_chr_ram_size_comes_from_nes2_header() {
+        // CNROM with 32K of CHR RAM and no CHR ROM
+        let mut raw = vec![
+            b'N', b'E', b'S', 0x1A, 2, 0, 0x30, 0x08, 0, 0, 0, 0x09, 0, 0, 0, 0,
+        ];
+        raw.resize(16 + 0x8000, 0);
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.chr_ram_size, 0x8000);
+
+        let mut bus = Bus::with_mapper(mapper::from_rom(rom).unwrap(), |_, _| {});
+        for bank in 0..4 {
+            bus.mem_write(0x8000, bank);
+            ppu_write(&mut bus, 0x0000, &[0x10 + bank]);
+        }
+        for bank in 0..4 {
+            bus.mem_write(0x8000, bank);
+            assert_eq!(bus.ppu.read_chr(0x0000), 0x10 + bank);
+        }
+
+        // iNES carts without CHR ROM get 8K
+        raw[7] = 0;
+        raw[11] = 0;
+        let rom = Rom::new(&raw).unwrap();
+        let mut bus = Bus::with_mapper(mapper::from_rom(rom).unwrap(), |_, _| {});
+        bus.mem_write(0x8000, 0);
+        ppu_write(&mut bus, 0x0000, &[0x55]);
+        bus.mem_write(0x8000, 1);
+        assert_eq!(bus.ppu.read_chr(0x0000), 0x55);
+    }
+
+    // 8 PRG banks and 4 CHR banks, each filled with its own bank number
+    fn test_mapper1() -> Mapper1 {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let chr_rom: Vec<u8> = (0..4).flat_map(|bank| vec![bank as u8; 0x1000]).collect();
+        Mapper1::new(prg_rom, chr_rom, true)
+    }
+
+    fn mmc1_write(mapper: &mut Mapper1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write_prg_byte(addr, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_mmc1_power_on_fixes_last_prg_bank() {
+        let mapper = test_mapper1();
+        assert_eq!(mapper.read_prg_byte(0x8000), 0);
+        assert_eq!(mapper.read_prg_byte(0xC000), 7);
+    }
+
+    #[test]
+    fn test_mmc1_prg_modes() {
+        let mut mapper = test_mapper1();
+
+        mmc1_write(&mut mapper, 0xE000, 3);
+        assert_eq!(mapper.read_prg_byte(0x8000), 3);
+        assert_eq!(mapper.read_prg_byte(0xC000), 7);
+
+        // fix first bank at $8000, switch $C000
+        mmc1_write(&mut mapper, 0x8000, 0b0_1000);
+        assert_eq!(mapper.read_prg_byte(0x8000), 0);
+        assert_eq!(mapper.read_prg_byte(0xC000), 3);
+
+        // 32K mode ignores the low bit
+        mmc1_write(&mut mapper, 0x8000, 0b0_0000);
+        assert_eq!(mapper.read_prg_byte(0x8000), 2);
+        assert_eq!(mapper.read_prg_byte(0xC000), 3);
+    }
+
+    #[test]
+    fn test_mmc1_reset_bit_clears_shift_register() {
+        let mut mapper = test_mapper1();
+        mapper.write_prg_byte(0xE000, 1);
+        mapper.write_prg_byte(0xE000, 1);
+        mapper.write_prg_byte(0xE000, 0x80);
+
+        mmc1_write(&mut mapper, 0xE000, 5);
+        assert_eq!(mapper.read_prg_byte(0x8000), 5);
+    }
+
+    #[test]
+    fn test_mmc1_chr_modes() {
+        let mut mapper = test_mapper1();
+
+        // 8K mode
+        mmc1_write(&mut mapper, 0xA000, 3);
+        assert_eq!(mapper.read_chr_byte(0x0000), 2);
+        assert_eq!(mapper.read_chr_byte(0x1000), 3);
+
+        // 4K mode
+        mmc1_write(&mut mapper, 0x8000, 0b1_1100);
+        mmc1_write(&mut mapper, 0xA000, 3);
+        mmc1_write(&mut mapper, 0xC000, 1);
+        assert_eq!(mapper.read_chr_byte(0x0000), 3);
+        assert_eq!(mapper.read_chr_byte(0x1000), 1);
+    }
+
+    #[test]
+    fn test_mmc1_mirroring_reaches_ppu() {
+        let mapper = Rc::new(RefCell::new(test_mapper1()));
+        let mut bus = Bus::with_mapper(mapper, |_, _| {});
+
+        for (mode, mirroring) in [
+            (0, Mirroring::SingleScreenLower),
+            (1, Mirroring::SingleScreenUpper),
+            (2, Mirroring::Vertical),
+            (3, Mirroring::Horizontal),
+        ] {
+            for bit in 0..5 {
+                bus.mem_write(0x8000, ((0b0_1100 | mode) >> bit) & 1);
+            }
+            assert_eq!(bus.ppu.mirroring(), mirroring);
+        }
+
+        // single screen maps every nametable onto the same 1K
+        assert_eq!(bus.ppu.mirror_vram_addr(0x2C05), 0x0405);
+    }
+
+    #[test]
+    fn test_mmc1_prg_ram() {
+        let mut mapper = test_mapper1();
+        mapper.write_prg_byte(0x6000, 0x42);
+        assert_eq!(mapper.read_prg_byte(0x6000), 0x42);
+        assert_eq!(mapper.battery_ram().unwrap()[0], 0x42);
+
+        // disabled through bit 4 of the PRG bank register
+        mmc1_write(&mut mapper, 0xE000, 0b1_0000);
+        mapper.write_prg_byte(0x6000, 0x43);
+        assert_eq!(mapper.read_prg_byte(0x6000), 0x42);
+    }
+
+    // 16 PRG banks of 8K and 16 CHR banks of 1K, each filled with its own bank number
+    fn test_mapper4() -> Mapper4 {
+        let prg_rom = (0..16).flat_map(|bank| vec![bank as u8; 0x2000]).collect();
+        let chr_rom: Vec<u8> = (0..16).flat_map(|bank| vec![bank as u8; 0x400]).collect();
+        Mapper4::new(prg_rom, chr_rom, Mirroring::Vertical, false)
+    }
+
+    #[test]
+    fn test_mmc3_prg_banking() {
+        let mut mapper = test_mapper4();
+        mapper.write_prg_byte(0x8000, 6);
+        mapper.write_prg_byte(0x8001, 3);
+        mapper.write_prg_byte(0x8000, 7);
+        mapper.write_prg_byte(0x8001, 5);
+
+        assert_eq!(mapper.read_prg_byte(0x8000), 3);
+        assert_eq!(mapper.read_prg_byte(0xA000), 5);
+        assert_eq!(mapper.read_prg_byte(0xC000), 14);
+        assert_eq!(mapper.read_prg_byte(0xE000), 15);
+
+        // PRG mode 1 swaps $8000 and $C000
+        mapper.write_prg_byte(0x8000, 0b0100_0000);
+        assert_eq!(mapper.read_prg_byte(0x8000), 14);
+        assert_eq!(mapper.read_prg_byte(0xC000), 3);
+        assert_eq!(mapper.read_prg_byte(0xE000), 15);
+    }
+
+    #[test]
+    fn test_mmc3_state_round_trip() {
+        let mut mapper = test_mapper4();
+        mapper.write_prg_byte(0x8000, 0b0100_0110);
+        mapper.write_prg_byte(0x8001, 3);
+        mapper.write_prg_byte(0xA000, 1);
+        mapper.write_prg_byte(0xC000, 7);
+        mapper.write_prg_byte(0xE001, 0);
+        mapper.write_prg_byte(0x6123, 0x42);
+
+        let mut state = StateWriter::new();
+        mapper.save(&mut state);
+        let state = state.finish();
+
+        let mut restored = test_mapper4();
+        // skip the save-state header
+        restored.load(&mut StateReader::new(&state[6..]));
+
+        assert_eq!(restored.read_prg_byte(0xC000), 3);
+        assert_eq!(restored.read_prg_byte(0x8000), 14);
+        assert_eq!(restored.read_prg_byte(0x6123), 0x42);
+        assert_eq!(restored.mirroring(), Mirroring::Horizontal);
+        for _ in 0..8 {
+            restored.signal_scanline();
+        }
+        assert!(restored.irq_pending());
+    }
+
+    #[test]
+    fn test_mmc3_chr_banking_and_inversion() {
+        let mut mapper = test_mapper4();
+        for (register, bank) in [(0, 8), (1, 10), (2, 1), (3, 2), (4, 3), (5, 4)] {
+            mapper.write_prg_byte(0x8000, register);
+            mapper.write_prg_byte(0x8001, bank);
+        }
+
+        assert_eq!(mapper.read_chr_byte(0x0000), 8);
+        assert_eq!(mapper.read_chr_byte(0x0400), 9);
+        assert_eq!(mapper.read_chr_byte(0x0800), 10);
+        assert_eq!(mapper.read_chr_byte(0x1000), 1);
+        assert_eq!(mapper.read_chr_byte(0x1C00), 4);
+
+        mapper.write_prg_byte(0x8000, 0b1000_0000);
+        assert_eq!(mapper.read_chr_byte(0x0000), 1);
+        assert_eq!(mapper.read_chr_byte(0x0C00), 4);
+        assert_eq!(mapper.read_chr_byte(0x1000), 8);
+        assert_eq!(mapper.read_chr_byte(0x1800), 10);
+    }
+
+    #[test]
+    fn test_mmc3_mirroring() {
+        let mut mapper = test_mapper4();
+        mapper.write_prg_byte(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.write_prg_byte(0xA000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        let mut mapper = test_mapper4();
+        mapper.write_prg_byte(0xC000, 2); // latch
+        mapper.write_prg_byte(0xC001, 0); // reload
+        mapper.write_prg_byte(0xE001, 0); // enable
+
+        mapper.signal_scanline(); // reload -> 2
+        mapper.signal_scanline(); // 1
+        assert!(!mapper.irq_pending());
+        mapper.signal_scanline(); // 0
+        assert!(mapper.irq_pending());
+
+        mapper.write_prg_byte(0xE000, 0); // acknowledge and disable
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_mmc3_counter_is_clocked_by_ppu_rendering() {
+        let mapper = Rc::new(RefCell::new(test_mapper4()));
+        let mut bus = Bus::with_mapper(mapper.clone(), |_, _| {});
+        bus.mem_write(0xC000, 0);
+        bus.mem_write(0xE001, 0);
+
+        // sprites at $1000, background at $0000, rendering enabled
+        bus.mem_write(0x2000, 0b0000_1000);
+        bus.mem_write(0x2001, 0b0001_1000);
+        assert!(!bus.irq_pending());
+
+        for _ in 0..100 {
+            bus.tick(1);
+        }
+        assert!(bus.irq_pending());
+    }
+
+    #[test]
+    fn test_cnrom_chr_banking() {
+        let prg_rom: Vec<u8> = vec![0; 0x8000];
+        let chr_rom: Vec<u8> = (0..4 * 0x2000).map(|i| (i / 0x2000) as u8).collect();
+        let mut mapper = mapper::Mapper3::new(prg_rom, chr_rom, Mirroring::Horizontal);
+
+        assert_eq!(mapper.read_chr_byte(0x0000), 0);
+        mapper.write_prg_byte(0x8000, 2);
+        assert_eq!(mapper.read_chr_byte(0x0000), 2);
+        assert_eq!(mapper.read_chr_byte(0x1FFF), 2);
+        // bank numbers wrap around the CHR ROM
+        mapper.write_prg_byte(0xFFFF, 5);
+        assert_eq!(mapper.read_chr_byte(0x0000), 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_ppu_status_writes_are_ignored() {
+        let mut bus = Bus::with_mapper(Rc::new(RefCell::new(test_mapper0())), |_, _| {});
+        bus.mem_write(0x2002, 0xFF);
+        assert_eq!(bus.ppu.registers.status.snapshot(), 0);
+    }
+}
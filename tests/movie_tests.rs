@@ -0,0 +1,179 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::Bus;
+    use nes::cpu::CPU;
+    use nes::joypad::JoypadButton;
+    use nes::movie::{Movie, Player, Recorder};
+    use nes::render;
+    use nes::render::frame::Frame;
+    use nes::rom::Rom;
+
+    const FM2: &str = "version 3\n\
+                       emuVersion 22020\n\
+                       rerecordCount 4\n\
+                       romFilename Balloon Fight (USA)\n\
+                       romChecksum base64:ZmFrZQ==\n\
+                       guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+                       comment author somebody\n\
+                       port0 1\n\
+                       port1 1\n\
+                       port2 0\n\
+                       |0|........|........||\n\
+                       |0|....T...|R......A||\n\
+                       |0|RL  ..BA|........||\n";
+
+    fn test_cpu() -> CPU<'static> {
+        let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+        let rom = Rom::new(&bytes).unwrap();
+        let mut cpu = CPU::new(Bus::new(rom, |_, _| {}).unwrap());
+        cpu.reset();
+        cpu
+    }
+
+    // scripted input: start the game, then fly up and to the right
+    fn buttons(frame: usize) -> [JoypadButton; 2] {
+        let port0 = match frame {
+            40..=45 => JoypadButton::START,
+            100..=179 if frame % 8 < 3 => JoypadButton::BUTTON_A | JoypadButton::RIGHT,
+            100..=179 => JoypadButton::RIGHT,
+            _ => JoypadButton::empty(),
+        };
+        [port0, JoypadButton::empty()]
+    }
+
+    fn record(cpu: &mut CPU, recorder: &mut Recorder, frames: usize) {
+        let start = cpu.bus.ppu.frame_count();
+        while cpu.bus.ppu.frame_count() < start + frames {
+            let frame = cpu.bus.ppu.frame_count() - start;
+            recorder.update(cpu, buttons(frame));
+            cpu.step();
+        }
+    }
+
+    fn play(movie: &Movie) -> CPU<'static> {
+        let mut cpu = test_cpu();
+        let mut player = Player::new(movie, &mut cpu).unwrap();
+        while player.update(&mut cpu) {
+            cpu.step();
+        }
+        cpu
+    }
+
+    fn frame_hash(cpu: &CPU) -> u64 {
+        let mut frame = Frame::new();
+        render::render(&cpu.bus.ppu, &mut frame);
+        frame.hash()
+    }
+
+    #[test]
+    fn test_fm2_import_and_export() {
+        let movie = Movie::from_fm2(FM2).unwrap();
+
+        assert_eq!(movie.rom_filename, "Balloon Fight (USA)");
+        assert_eq!(movie.rom_checksum.as_deref(), Some("base64:ZmFrZQ=="));
+        assert_eq!(movie.rerecords, 4);
+        assert_eq!(movie.start, None);
+        assert_eq!(
+            movie.frames,
+            vec![
+                [JoypadButton::empty(), JoypadButton::empty()],
+                [
+                    JoypadButton::START,
+                    JoypadButton::RIGHT | JoypadButton::BUTTON_A
+                ],
+                [
+                    JoypadButton::RIGHT
+                        | JoypadButton::LEFT
+                        | JoypadButton::BUTTON_B
+                        | JoypadButton::BUTTON_A,
+                    JoypadButton::empty()
+                ],
+            ]
+        );
+
+        let text = movie.to_fm2();
+        assert!(text.contains("|0|....T...|R......A||\n"));
+        assert_eq!(Movie::from_fm2(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_unsupported_fm2_features_are_rejected() {
+        let fourscore = FM2.replace("port2 0", "fourscore 1");
+        assert!(Movie::from_fm2(&fourscore).is_err());
+
+        let zapper = FM2.replace("port1 1", "port1 2");
+        assert!(Movie::from_fm2(&zapper).is_err());
+
+        let reset = FM2.replace("|0|RL", "|1|RL");
+        assert_eq!(
+            Movie::from_fm2(&reset).unwrap_err(),
+            "line 13: reset and power commands are not supported"
+        );
+
+        let fceux_state = format!("{}savestate base64:AAAA\n", FM2);
+        assert!(Movie::from_fm2(&fceux_state).is_err());
+    }
+
+    #[test]
+    fn test_power_on_movie_replays_identically() {
+        let mut cpu = test_cpu();
+        let mut recorder = Recorder::power_on(&cpu, "Balloon Fight (USA).nes");
+        record(&mut cpu, &mut recorder, 200);
+        let movie = Movie::from_fm2(&recorder.finish().to_fm2()).unwrap();
+        assert_eq!(movie.frames.len(), 200);
+
+        let replayed = play(&movie);
+
+        assert_eq!(replayed.bus.ppu.frame_count(), cpu.bus.ppu.frame_count());
+        assert_eq!(frame_hash(&replayed), frame_hash(&cpu));
+        assert_eq!(replayed.save_state(), cpu.save_state());
+        // the input made a difference
+        assert_ne!(frame_hash(&cpu), frame_hash(&play(&Movie::default())));
+    }
+
+    #[test]
+    fn test_movie_from_save_state_and_rerecords() {
+        let mut cpu = test_cpu();
+        for _ in 0..50_000 {
+            cpu.step();
+        }
+        let mut recorder = Recorder::from_state(&cpu, "Balloon Fight (USA).nes");
+        let rewind_point = cpu.save_state();
+        record(&mut cpu, &mut recorder, 60);
+
+        // rewinding while recording drops the frames after the restored one
+        cpu.load_state(&rewind_point).unwrap();
+        record(&mut cpu, &mut recorder, 120);
+        let movie = recorder.finish();
+        assert_eq!(movie.rerecords, 1);
+        assert_eq!(movie.frames.len(), 120);
+
+        let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+        let replayed = play(&movie);
+        assert_eq!(replayed.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn test_headless_replay_prints_only_the_frame_hash() {
+        let mut cpu = test_cpu();
+        let mut recorder = Recorder::power_on(&cpu, "Balloon Fight (USA).nes");
+        record(&mut cpu, &mut recorder, 120);
+        let movie = recorder.finish();
+        let path = std::env::temp_dir().join(format!("nes-movie-{}.fm2", std::process::id()));
+        std::fs::write(&path, movie.to_fm2()).unwrap();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_nes-headless"))
+            .arg("src/samples/Balloon Fight (USA).nes")
+            .arg("--movie")
+            .arg(&path)
+            .arg("--frame-hash")
+            .output()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, format!("{:016x}\n", frame_hash(&play(&movie))));
+    }
+}
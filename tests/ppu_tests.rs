@@ -2,7 +2,11 @@
 
 mod tests {
     use nes::ppu::PPU;
+    use nes::render;
+    use nes::render::frame::Frame;
+    use nes::render::palette::{EMPHASIS_PALETTE, SYSTEM_PALLETE};
     use nes::rom::Mirroring;
+    use nes::savestate::{Snapshot, StateReader, StateWriter};
 
     #[test]
     fn test_ppu_vram_writes() {
@@ -24,7 +28,7 @@ mod tests {
         ppu.registers.write_to_ppu_addr(0x05);
 
         ppu.read_data(); //load_into_buffer
-        assert_eq!(ppu.registers.addr.get(), 0x2306);
+        assert_eq!(ppu.registers.scroll.addr(), 0x2306);
         assert_eq!(ppu.read_data(), 0x66);
     }
 
@@ -154,6 +158,60 @@ mod tests {
         // assert_eq!(ppu.addr.read(), 0x0306)
     }
 
+    #[test]
+    fn test_ppu_vram_writes_to_mirrors() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.registers.write_control(0);
+
+        ppu.registers.write_to_ppu_addr(0x30); //0x3005 -> 0x2005
+        ppu.registers.write_to_ppu_addr(0x05);
+        ppu.write_to_data(0x66);
+        assert_eq!(ppu.vram[0x0005], 0x66);
+        assert_eq!(ppu.peek(0x2005), 0x66);
+
+        ppu.registers.write_to_ppu_addr(0x3F); //0x3F25 -> 0x3F05
+        ppu.registers.write_to_ppu_addr(0x25);
+        ppu.write_to_data(0x16);
+        assert_eq!(ppu.palette_table[0x05], 0x16);
+
+        ppu.registers.write_to_ppu_addr(0x3F); //0x3F30 -> 0x3F10 -> 0x3F00
+        ppu.registers.write_to_ppu_addr(0x30);
+        ppu.write_to_data(0x0F);
+        assert_eq!(ppu.palette_table[0x00], 0x0F);
+    }
+
+    #[test]
+    fn test_four_screen_nametables() {
+        let mut ppu = PPU::new(vec![0; 0x2000], Mirroring::FourScreen);
+        ppu.registers.write_control(0);
+
+        for (table, value) in [0x66, 0x77, 0x88, 0x99].into_iter().enumerate() {
+            ppu.registers.write_to_ppu_addr(0x20 + table as u8 * 4);
+            ppu.registers.write_to_ppu_addr(0x05);
+            ppu.write_to_data(value);
+        }
+
+        for (table, value) in [0x66, 0x77, 0x88, 0x99].into_iter().enumerate() {
+            let addr = 0x2005 + table as u16 * 0x400;
+            assert_eq!(ppu.peek(addr), value);
+            // $3000-$3EFF mirrors the four tables too
+            assert_eq!(ppu.peek(addr + 0x1000), value);
+
+            ppu.registers.write_to_ppu_addr((addr >> 8) as u8);
+            ppu.registers.write_to_ppu_addr(addr as u8);
+            ppu.read_data(); //load into_buffer
+            assert_eq!(ppu.read_data(), value);
+        }
+
+        let mut state = StateWriter::new();
+        ppu.save(&mut state);
+        let state = state.finish();
+        let mut restored = PPU::new(vec![0; 0x2000], Mirroring::FourScreen);
+        // skip the save-state header
+        restored.load(&mut StateReader::new(&state[6..]));
+        assert_eq!(restored.peek(0x2C05), 0x99);
+    }
+
     #[test]
     fn test_read_status_resets_vblank() {
         let mut ppu = PPU::new_empty_rom();
@@ -165,6 +223,33 @@ mod tests {
         assert_eq!(ppu.registers.status.snapshot() >> 7, 0);
     }
 
+    #[test]
+    fn test_peek_leaves_ppu_state_alone() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.registers.write_control(0);
+        ppu.registers.status.set_vblank_status(true);
+        ppu.vram[0x0305] = 0x66;
+        ppu.palette_table[0x00] = 0x0F;
+
+        ppu.registers.write_to_ppu_addr(0x23);
+        ppu.registers.write_to_ppu_addr(0x05);
+        ppu.read_data(); //load_into_buffer
+
+        assert_eq!(ppu.registers.peek_status() >> 7, 1);
+        assert_eq!(ppu.peek_register(0x2002) >> 7, 1);
+        assert_eq!(ppu.peek_data(), 0x66);
+        assert_eq!(ppu.peek_data(), 0x66);
+        assert_eq!(ppu.registers.scroll.addr(), 0x2306);
+
+        assert_eq!(ppu.peek(0x2305), 0x66);
+        assert_eq!(ppu.peek(0x3305), 0x66); // $3000-$3EFF mirrors the name tables
+        assert_eq!(ppu.peek(0x3F10), 0x0F); // sprite backdrop mirrors $3F00
+        assert_eq!(ppu.peek(0x3F20), 0x0F);
+
+        assert_eq!(ppu.read_data(), 0x66);
+        assert_eq!(ppu.registers.read_status() >> 7, 1);
+    }
+
     #[test]
     fn test_oam_read_write() {
         let mut ppu = PPU::new_empty_rom();
@@ -198,4 +283,354 @@ mod tests {
         ppu.registers.write_to_oam_addr(0x11);
         ppu.registers.write_to_oam_addr(0x66);
     }
+
+    // CHR with a solid tile 1 in color 1 and a solid tile 2 in color 3
+    fn test_chr() -> Vec<u8> {
+        let mut chr = vec![0; 0x2000];
+        chr[0x10..0x18].fill(0xFF);
+        chr[0x20..0x30].fill(0xFF);
+        chr
+    }
+
+    fn run_to_scanline(ppu: &mut PPU, scanline: u16) {
+        while ppu.scanline() != scanline {
+            ppu.tick(1);
+        }
+    }
+
+    fn next_frame(ppu: &mut PPU) {
+        run_to_scanline(ppu, 240);
+        run_to_scanline(ppu, 241);
+    }
+
+    #[test]
+    fn test_palette_change_mid_frame() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.palette_table[1] = 0x16;
+        ppu.registers.write_to_mask(0b0000_1010);
+        next_frame(&mut ppu);
+
+        run_to_scanline(&mut ppu, 120);
+        ppu.palette_table[1] = 0x2A;
+        next_frame(&mut ppu);
+
+        let frame = ppu.frame();
+        assert_eq!(frame.len(), 256 * 240);
+        assert!(frame[..120 * 256].iter().all(|color| *color == 0x16));
+        assert!(frame[120 * 256..].iter().all(|color| *color == 0x2A));
+    }
+
+    #[test]
+    fn test_sprites_are_drawn_from_oam() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[0x17] = 0x30;
+        // sprite 0: Y 9 (drawn from line 10), tile 2, palette 1, X 20
+        ppu.write_oam_dma(&{
+            let mut oam = [0xFF; 256];
+            oam[0..4].copy_from_slice(&[9, 2, 0b01, 20]);
+            oam
+        });
+        ppu.registers.write_to_mask(0b0001_0000);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+
+        let frame = ppu.frame();
+        let at = |x: usize, y: usize| frame[y * 256 + x];
+        assert_eq!(at(20, 10), 0x30);
+        assert_eq!(at(27, 17), 0x30);
+        assert_eq!(at(19, 10), 0x0F);
+        assert_eq!(at(28, 10), 0x0F);
+        assert_eq!(at(20, 9), 0x0F);
+        assert_eq!(at(20, 18), 0x0F);
+    }
+
+    #[test]
+    fn test_scroll_and_addr_share_write_toggle() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.registers.write_control(0);
+        ppu.registers.read_status();
+
+        ppu.registers.write_to_scroll(0x7D);
+        assert_eq!(ppu.registers.scroll.t, 0x000F);
+        assert_eq!(ppu.registers.scroll.x, 0b101);
+        ppu.registers.write_to_scroll(0x5E);
+        assert_eq!(ppu.registers.scroll.t, 0x616F);
+        ppu.registers.write_to_ppu_addr(0x3D);
+        ppu.registers.write_to_ppu_addr(0xF0);
+        assert_eq!(ppu.registers.scroll.t, 0x3DF0);
+        assert_eq!(ppu.registers.scroll.v, 0x3DF0);
+
+        // the second write of a $2005 pair is taken as the low byte of $2006
+        ppu.registers.write_to_scroll(0x00);
+        ppu.registers.write_to_ppu_addr(0x20);
+        assert_eq!(ppu.registers.scroll.v, 0x3D20);
+
+        ppu.registers.write_to_scroll(0x00);
+        ppu.registers.read_status();
+        ppu.registers.write_to_ppu_addr(0x24);
+        ppu.registers.write_to_ppu_addr(0x00);
+        assert_eq!(ppu.registers.scroll.addr(), 0x2400);
+
+        ppu.registers.write_control(0b10);
+        assert_eq!(ppu.registers.scroll.t & 0x0C00, 0x0800);
+    }
+
+    #[test]
+    fn test_scroll_split_mid_frame() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Vertical);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.vram[0x400..0x7C0].fill(2);
+        ppu.palette_table[1] = 0x16;
+        ppu.palette_table[3] = 0x2A;
+        ppu.registers.write_to_mask(0b0000_1010);
+
+        ppu.registers.write_control(0b01);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert!(ppu.frame().iter().all(|color| *color == 0x2A));
+
+        ppu.registers.write_control(0b00);
+        next_frame(&mut ppu);
+        run_to_scanline(&mut ppu, 100);
+        ppu.registers.write_to_ppu_addr(0x04);
+        ppu.registers.write_to_ppu_addr(0x00);
+        next_frame(&mut ppu);
+
+        let frame = ppu.frame();
+        assert!(frame[..100 * 256].iter().all(|color| *color == 0x16));
+        assert!(frame[101 * 256..].iter().all(|color| *color == 0x2A));
+    }
+
+    fn oam_with(sprites: &[[u8; 4]]) -> [u8; 256] {
+        let mut oam = [0xFF; 256];
+        for (i, sprite) in sprites.iter().enumerate() {
+            oam[i * 4..i * 4 + 4].copy_from_slice(sprite);
+        }
+        oam
+    }
+
+    #[test]
+    fn test_eight_sprites_per_line_and_overflow() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.palette_table[0x13] = 0x30;
+        let mut sprites: Vec<[u8; 4]> = (0..8).map(|i| [9, 2, 0, i * 16]).collect();
+        ppu.write_oam_dma(&oam_with(&sprites));
+        ppu.registers.write_to_mask(0b0001_0000);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert_eq!(ppu.registers.peek_status() & 0x20, 0);
+
+        sprites.push([9, 2, 0, 200]);
+        ppu.write_oam_dma(&oam_with(&sprites));
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+
+        let frame = ppu.frame();
+        assert_eq!(frame[10 * 256 + 112], 0x30);
+        assert_eq!(frame[10 * 256 + 200], 0x00);
+        assert_eq!(ppu.registers.peek_status() & 0x20, 0x20);
+    }
+
+    #[test]
+    fn test_overflow_scans_diagonally() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        // eight sprites on lines 10-17, then an entry off screen and one whose
+        // tile byte is in range: after eight hits the scan reads it as Y
+        let mut sprites: Vec<[u8; 4]> = (0..8).map(|i| [9, 2, 0, i * 16]).collect();
+        sprites.push([200, 0, 0, 0]);
+        sprites.push([200, 9, 0, 0]);
+        ppu.write_oam_dma(&oam_with(&sprites));
+        ppu.registers.write_to_mask(0b0001_0000);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert_eq!(ppu.registers.peek_status() & 0x20, 0x20);
+
+        // a tenth sprite really on the line goes unnoticed for the same reason
+        sprites[9] = [9, 200, 200, 200];
+        ppu.write_oam_dma(&oam_with(&sprites));
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert_eq!(ppu.registers.peek_status() & 0x20, 0x00);
+    }
+
+    #[test]
+    fn test_8x16_sprites_use_the_tile_bank() {
+        let mut chr = vec![0; 0x2000];
+        chr[0x1020..0x1030].fill(0xFF); // tile $102: color 3
+        chr[0x1030..0x1038].fill(0xFF); // tile $103: color 1
+        let mut ppu = PPU::new(chr, Mirroring::Horizontal);
+        ppu.palette_table[0x11] = 0x16;
+        ppu.palette_table[0x13] = 0x2A;
+        ppu.write_oam_dma(&oam_with(&[[9, 0x03, 0, 20], [39, 0x03, 0x80, 20]]));
+        ppu.registers.write_control(0b0010_0000);
+        ppu.registers.write_to_mask(0b0001_0000);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+
+        let frame = ppu.frame();
+        assert_eq!(frame[10 * 256 + 20], 0x2A);
+        assert_eq!(frame[17 * 256 + 20], 0x2A);
+        assert_eq!(frame[18 * 256 + 20], 0x16);
+        assert_eq!(frame[25 * 256 + 20], 0x16);
+        assert_eq!(frame[26 * 256 + 20], 0x00);
+        // flipped vertically across both tiles
+        assert_eq!(frame[40 * 256 + 20], 0x16);
+        assert_eq!(frame[48 * 256 + 20], 0x2A);
+    }
+
+    #[test]
+    fn test_sprite_priority_and_sprite_zero_hit() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.vram[0..0x40].fill(0); // the top two tile rows are transparent
+        ppu.palette_table[1] = 0x16;
+        ppu.palette_table[0x13] = 0x30;
+        ppu.registers.write_to_mask(0b0001_1000);
+
+        // sprite 0 over the transparent row, behind-background sprite below it
+        ppu.write_oam_dma(&oam_with(&[[0, 2, 0, 20], [19, 2, 0x20, 20]]));
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        let frame = ppu.frame();
+        assert_eq!(frame[256 + 20], 0x30);
+        assert_eq!(frame[20 * 256 + 20], 0x16);
+        assert_eq!(ppu.registers.peek_status() & 0x40, 0);
+
+        // sprite 0 overlapping opaque background
+        ppu.write_oam_dma(&oam_with(&[[19, 2, 0, 20]]));
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert_eq!(ppu.frame()[20 * 256 + 20], 0x30);
+        assert_eq!(ppu.registers.peek_status() & 0x40, 0x40);
+
+        run_to_scanline(&mut ppu, 0);
+        assert_eq!(ppu.registers.peek_status() & 0x40, 0);
+    }
+
+    #[test]
+    fn test_status_flags_clear_at_pre_render_dot_1() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.registers.write_to_mask(0b0001_1000);
+        ppu.write_oam_dma(&oam_with(&[[19, 2, 0, 20]]));
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+
+        run_to_scanline(&mut ppu, 261);
+        ppu.tick(1);
+        assert_eq!(ppu.dot(), 1);
+        assert_eq!(ppu.registers.peek_status() & 0xC0, 0xC0);
+
+        ppu.tick(1);
+        assert_eq!(ppu.registers.peek_status() & 0xC0, 0);
+        assert_eq!(ppu.scanline(), 261);
+    }
+
+    #[test]
+    fn test_render_frame_layout() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Vertical);
+        ppu.vram[0x400..0x7C0].fill(1); // second nametable, shown in the debug view
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[1] = 0x16;
+        ppu.palette_table[0x13] = 0x30;
+        ppu.write_oam_dma(&oam_with(&[[99, 2, 0, 200]]));
+        ppu.registers.write_to_mask(0b0001_1000);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+
+        let mut frame = Frame::new();
+        render::render(&ppu, &mut frame);
+        assert_eq!((frame.width(), frame.height()), (256, 240));
+        assert_eq!(frame.data.len(), 256 * 240 * 3);
+        assert_eq!(frame.pitch(), 256 * 3);
+        let (r, g, b) = SYSTEM_PALLETE[0x30];
+        assert_eq!(&frame.data[(100 * 256 + 200) * 3..][..3], &[r, g, b]);
+        assert_eq!(frame.pixel(200, 100), SYSTEM_PALLETE[0x30]);
+        assert_eq!(frame.pixel(199, 100), SYSTEM_PALLETE[0x0F]);
+
+        let mut debug = Frame::with_size(render::DEBUG_WIDTH, render::DEBUG_HEIGHT);
+        render::render_debug(&ppu, &mut debug);
+        assert_eq!(debug.pixel(200, 100), SYSTEM_PALLETE[0x30]);
+        assert_eq!(debug.pixel(256 + 10, 10), SYSTEM_PALLETE[0x0F]);
+        assert_eq!(debug.pixel(256 + 138, 10), SYSTEM_PALLETE[0x16]);
+        // vertical mirroring: the bottom row repeats the top one
+        assert_eq!(debug.pixel(256 + 10, 130), SYSTEM_PALLETE[0x0F]);
+        assert_eq!(debug.pixel(256 + 138, 130), SYSTEM_PALLETE[0x16]);
+    }
+
+    #[test]
+    fn test_mask_clips_and_hides_layers() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[1] = 0x16;
+        ppu.palette_table[0x13] = 0x30;
+        ppu.write_oam_dma(&oam_with(&[[49, 2, 0x20, 0], [99, 2, 0, 4]]));
+        let frame_with_mask = |ppu: &mut PPU, mask: u8| {
+            ppu.registers.write_to_mask(mask);
+            next_frame(ppu);
+            next_frame(ppu);
+            ppu.frame().to_vec()
+        };
+
+        // both layers, left columns clipped: sprite 0 over the background only
+        // from x = 8 on, so no hit
+        let frame = frame_with_mask(&mut ppu, 0b0001_1000);
+        assert_eq!(frame[50 * 256 + 4], 0x0F);
+        assert_eq!(frame[100 * 256 + 4], 0x0F);
+        assert_eq!(frame[100 * 256 + 8], 0x30);
+        assert_eq!(frame[100 * 256 + 12], 0x16);
+        assert_eq!(ppu.registers.peek_status() & 0x40, 0);
+
+        let frame = frame_with_mask(&mut ppu, 0b0001_1110);
+        assert_eq!(frame[50 * 256 + 4], 0x16);
+        assert_eq!(frame[100 * 256 + 4], 0x30);
+        assert_eq!(ppu.registers.peek_status() & 0x40, 0x40);
+
+        // one layer at a time
+        let frame = frame_with_mask(&mut ppu, 0b0001_0110);
+        assert_eq!(frame[50 * 256 + 4], 0x30);
+        assert_eq!(frame[10 * 256 + 100], 0x0F);
+        let frame = frame_with_mask(&mut ppu, 0b0000_1110);
+        assert_eq!(frame[100 * 256 + 4], 0x16);
+
+        // rendering off: the backdrop, or the palette entry v points at
+        let frame = frame_with_mask(&mut ppu, 0);
+        assert!(frame.iter().all(|pixel| *pixel == 0x0F));
+        ppu.registers.write_to_ppu_addr(0x3F);
+        ppu.registers.write_to_ppu_addr(0x01);
+        let frame = frame_with_mask(&mut ppu, 0);
+        assert!(frame.iter().all(|pixel| *pixel == 0x16));
+    }
+
+    #[test]
+    fn test_mask_greyscale_and_emphasis() {
+        let mut ppu = PPU::new(test_chr(), Mirroring::Horizontal);
+        ppu.vram[0..0x3C0].fill(1);
+        ppu.palette_table[1] = 0x16;
+
+        ppu.registers.write_to_mask(0b0000_1011);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert!(ppu.frame().iter().all(|pixel| *pixel == 0x10));
+
+        ppu.registers.write_to_mask(0b0010_1010);
+        next_frame(&mut ppu);
+        next_frame(&mut ppu);
+        assert!(ppu.frame().iter().all(|pixel| *pixel == 1 << 6 | 0x16));
+
+        let mut frame = Frame::new();
+        render::render(&ppu, &mut frame);
+        let (r, g, b) = SYSTEM_PALLETE[0x16];
+        let (er, eg, eb) = EMPHASIS_PALETTE[1 << 6 | 0x16];
+        assert_eq!(frame.pixel(0, 0), (er, eg, eb));
+        assert_eq!(er, r);
+        assert!(eg < g || g == 0);
+        assert!(eb < b || b == 0);
+        assert_eq!(EMPHASIS_PALETTE[0x16], SYSTEM_PALLETE[0x16]);
+        let white = SYSTEM_PALLETE[0x30].0 as u16;
+        assert_eq!(EMPHASIS_PALETTE[7 << 6 | 0x30].0 as u16, white * 209 / 256);
+    }
 }
@@ -0,0 +1,441 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::{mapper, Bus};
+    use nes::cpu::CPU;
+    use nes::rom::{ConsoleType, ExpansionDevice, HeaderFormat, Mirroring, Rom, RomError, Timing};
+    use std::fmt;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+
+    // drop blargg-style ROMs here, nestest.nes is compared against nestest.log next to it
+    const ROM_DIR: &str = "tests/roms";
+    const BUNDLED_ROMS: [&str; 1] = ["src/samples/cpu_dummy_reads.nes"];
+
+    const STATUS_ADDR: u16 = 0x6000;
+    const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+    const STATUS_RUNNING: u8 = 0x80;
+    const STATUS_NEEDS_RESET: u8 = 0x81;
+    // the protocol asks for at least 100ms between the reset request and the reset
+    const RESET_DELAY_FRAMES: usize = 6;
+    const TIMEOUT_FRAMES: usize = 60 * 60;
+
+    enum Outcome {
+        Passed,
+        Failed(String),
+        Skipped(String),
+    }
+
+    impl fmt::Display for Outcome {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Outcome::Passed => write!(f, "PASS"),
+                Outcome::Failed(reason) => write!(f, "FAIL  {}", reason),
+                Outcome::Skipped(reason) => write!(f, "SKIP  {}", reason),
+            }
+        }
+    }
+
+    fn load(path: &Path) -> Result<CPU<'static>, String> {
+        let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+        let rom = Rom::new(&bytes).map_err(|err| err.to_string())?;
+        let mapper = mapper::from_rom(rom).map_err(|err| err.to_string())?;
+        let bus = Bus::with_mapper(mapper, |_, _| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        Ok(cpu)
+    }
+
+    // peeks so polling never disturbs the test
+    fn cart_byte(cpu: &CPU, addr: u16) -> u8 {
+        cpu.bus.peek(addr)
+    }
+
+    fn cart_text(cpu: &CPU) -> String {
+        let text: Vec<u8> = (STATUS_ADDR + 4..0x8000)
+            .map(|addr| cart_byte(cpu, addr))
+            .take_while(|&byte| byte != 0)
+            .collect();
+        String::from_utf8_lossy(&text).trim().replace('\n', " / ")
+    }
+
+    // older blargg ROMs only print their result, with tiles numbered by ASCII code
+    fn screen_text(cpu: &CPU) -> Vec<String> {
+        cpu.bus.ppu.vram[..0x3C0]
+            .chunks(32)
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .map(|&tile| match tile {
+                        0x20..=0x7E => tile as char,
+                        _ => ' ',
+                    })
+                    .collect();
+                line.trim().to_string()
+            })
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    fn run_frame(cpu: &mut CPU) {
+        let frame = cpu.bus.ppu.frame_count();
+        while cpu.bus.ppu.frame_count() == frame {
+            cpu.step();
+        }
+    }
+
+    /// Runs a ROM that reports through the $6000 status byte: $80 while running,
+    /// $81 to request a reset and anything below $80 as the final result code,
+    /// followed by a zero-terminated message at $6004. ROMs that never write the
+    /// signature are judged by the "Passed"/"Failed" line they print on screen.
+    fn run_blargg(path: &Path) -> Outcome {
+        let mut cpu = match load(path) {
+            Ok(cpu) => cpu,
+            Err(err) => return Outcome::Failed(err),
+        };
+
+        let mut reset_at = None;
+        for frame in 0..TIMEOUT_FRAMES {
+            run_frame(&mut cpu);
+
+            let signature = [1, 2, 3].map(|i| cart_byte(&cpu, STATUS_ADDR + i));
+            if signature != SIGNATURE {
+                let text = screen_text(&cpu);
+                if text.iter().any(|line| line == "Passed") {
+                    return Outcome::Passed;
+                }
+                if text.iter().any(|line| line.starts_with("Failed")) {
+                    return Outcome::Failed(text.join(" / "));
+                }
+                continue;
+            }
+
+            match cart_byte(&cpu, STATUS_ADDR) {
+                STATUS_RUNNING => {}
+                STATUS_NEEDS_RESET => match reset_at {
+                    None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+                    Some(at) if frame >= at => {
+                        cpu.reset();
+                        reset_at = None;
+                    }
+                    Some(_) => {}
+                },
+                0 => return Outcome::Passed,
+                code => return Outcome::Failed(format!("#{}: {}", code, cart_text(&cpu))),
+            }
+        }
+        Outcome::Failed(format!("timed out after {} frames", TIMEOUT_FRAMES))
+    }
+
+    struct TraceLine {
+        pc: u16,
+        a: u8,
+        x: u8,
+        y: u8,
+        p: u8,
+        sp: u8,
+        cyc: usize,
+    }
+
+    impl fmt::Display for TraceLine {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                self.pc, self.a, self.x, self.y, self.p, self.sp, self.cyc
+            )
+        }
+    }
+
+    fn parse_trace_line(line: &str) -> Option<TraceLine> {
+        let field = |name: &str| {
+            let start = line.find(name)? + name.len();
+            let value = line[start..].split_whitespace().next()?;
+            Some(value.to_string())
+        };
+        let hex = |name: &str| u8::from_str_radix(&field(name)?, 16).ok();
+
+        Some(TraceLine {
+            pc: u16::from_str_radix(line.get(0..4)?, 16).ok()?,
+            a: hex("A:")?,
+            x: hex("X:")?,
+            y: hex("Y:")?,
+            p: hex("P:")?,
+            sp: hex("SP:")?,
+            cyc: field("CYC:")?.parse().ok()?,
+        })
+    }
+
+    /// Runs nestest in automation mode (entry at $C000) and compares the CPU state
+    /// before every instruction with the golden log.
+    fn run_nestest(rom: &Path, log: &Path) -> Outcome {
+        let golden = match std::fs::read_to_string(log) {
+            Ok(golden) => golden,
+            Err(_) => return Outcome::Skipped(format!("{} not found", log.display())),
+        };
+        let mut cpu = match load(rom) {
+            Ok(cpu) => cpu,
+            Err(err) => return Outcome::Failed(err),
+        };
+        cpu.pc = 0xC000;
+
+        let mut cycle_offset = None;
+        for (number, line) in golden.lines().enumerate() {
+            let expected = match parse_trace_line(line) {
+                Some(expected) => expected,
+                None => return Outcome::Failed(format!("line {} is not a trace line", number + 1)),
+            };
+            // the log starts counting after the reset sequence
+            let offset = *cycle_offset.get_or_insert(expected.cyc - cpu.bus.cycles());
+
+            let actual = TraceLine {
+                pc: cpu.pc,
+                a: cpu.a,
+                x: cpu.x,
+                y: cpu.y,
+                p: cpu.status.bits(),
+                sp: cpu.sp,
+                cyc: cpu.bus.cycles() + offset,
+            };
+            if actual.to_string() != expected.to_string() {
+                return Outcome::Failed(format!(
+                    "line {}: expected {}, got {}",
+                    number + 1,
+                    expected,
+                    actual
+                ));
+            }
+            cpu.step();
+        }
+        Outcome::Passed
+    }
+
+    fn test_roms() -> Vec<PathBuf> {
+        let mut roms: Vec<PathBuf> = BUNDLED_ROMS.iter().map(PathBuf::from).collect();
+        if let Ok(entries) = std::fs::read_dir(ROM_DIR) {
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+                .collect();
+            found.sort();
+            roms.extend(found);
+        }
+        roms
+    }
+
+    #[test]
+    fn test_rom_suite() {
+        let mut results = Vec::new();
+        for rom in test_roms() {
+            let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                if rom.file_stem().is_some_and(|stem| stem == "nestest") {
+                    run_nestest(&rom, &rom.with_extension("log"))
+                } else {
+                    run_blargg(&rom)
+                }
+            }));
+            let outcome = run.unwrap_or_else(|panic| {
+                let message = panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                Outcome::Failed(format!("panicked: {}", message))
+            });
+            results.push((rom, outcome));
+        }
+
+        let width = results
+            .iter()
+            .map(|(rom, _)| rom.display().to_string().len())
+            .max()
+            .unwrap_or(0);
+        println!();
+        for (rom, outcome) in &results {
+            println!("{:width$}  {}", rom.display(), outcome, width = width);
+        }
+
+        let failed = results
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+            .count();
+        assert_eq!(
+            failed,
+            0,
+            "{} of {} test ROMs failed",
+            failed,
+            results.len()
+        );
+    }
+
+    // a header followed by PRG and CHR data of the given sizes
+    fn image(header: [u8; 16], prg_size: usize, chr_size: usize) -> Vec<u8> {
+        let mut raw = header.to_vec();
+        raw.extend((0..prg_size).map(|i| i as u8));
+        raw.resize(raw.len() + chr_size, 0xCC);
+        raw
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let header = [
+            b'N', b'E', b'S', 0x1A, 2, 1, 0x13, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let rom = Rom::new(&image(header, 0x8000, 0x2000)).unwrap();
+
+        assert_eq!(rom.format, HeaderFormat::INes);
+        assert_eq!(rom.mapper, 0x41);
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+        assert_eq!((rom.prg_rom.len(), rom.chr_rom.len()), (0x8000, 0x2000));
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0, 0x2000));
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.timing, Timing::Ntsc);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let header = [
+            b'N', b'E', b'S', 0x1A, //
+            0x02, 0x00, // 2 x 16K PRG, CHR RAM only
+            0x40, // mapper low nibble 4
+            0x0A, // NES 2.0, mapper bits 4-7 = 0, Playchoice 10
+            0x31, // submapper 3, mapper bits 8-11 = 1
+            0x00, // no size MSBs
+            0x70, // no PRG RAM, 8K battery-backed PRG RAM
+            0x07, // 8K CHR RAM
+            0x01, // PAL
+            0x00, 0x00, //
+            0x08, // Zapper
+        ];
+        let rom = Rom::new(&image(header, 0x8000, 0)).unwrap();
+
+        assert_eq!(rom.format, HeaderFormat::Nes2);
+        assert_eq!(rom.mapper, 0x104);
+        assert_eq!(rom.submapper, 3);
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0, 0x2000));
+        assert_eq!((rom.chr_ram_size, rom.chr_nvram_size), (0x2000, 0));
+        assert_eq!(rom.console_type, ConsoleType::Playchoice10);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert_eq!(rom.expansion_device, ExpansionDevice::Zapper);
+    }
+
+    #[test]
+    fn test_nes2_rom_sizes() {
+        let mut header = [
+            b'N', b'E', b'S', 0x1A, 0, 0, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        header[4] = 0x01;
+        header[5] = 0x02;
+        header[9] = 0x10; // CHR MSB 1: 258 x 8K
+        let rom = Rom::new(&image(header, 0x4000, 258 * 0x2000)).unwrap();
+        assert_eq!(rom.chr_rom.len(), 258 * 0x2000);
+
+        // exponent-multiplier: 2^10 * (1 * 2 + 1) = 3K of PRG
+        header[4] = (10 << 2) | 1;
+        header[5] = 0;
+        header[9] = 0x0F;
+        let rom = Rom::new(&image(header, 3 * 1024, 0)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 3 * 1024);
+        assert_eq!(rom.prg_rom[3 * 1024 - 1], (3 * 1024 - 1) as u8);
+    }
+
+    #[test]
+    fn test_invalid_images_are_errors() {
+        let header = [
+            b'N', b'E', b'S', 0x1A, 2, 1, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let raw = image(header, 512 + 0x8000, 0x2000);
+
+        assert!(matches!(
+            Rom::new(b"NE"),
+            Err(RomError::TooShort { len: 2 })
+        ));
+        assert!(matches!(
+            Rom::new(&raw[..10]),
+            Err(RomError::TooShort { len: 10 })
+        ));
+        assert!(matches!(Rom::new(b"PK\x03\x04"), Err(RomError::BadMagic)));
+        assert!(matches!(
+            Rom::new(&raw[..100]),
+            Err(RomError::TruncatedTrainer)
+        ));
+        assert!(matches!(
+            Rom::new(&raw[..16 + 512 + 0x1000]),
+            Err(RomError::TruncatedPrg {
+                expected: 0x8000,
+                found: 0x1000
+            })
+        ));
+        assert!(matches!(
+            Rom::new(&raw[..raw.len() - 1]),
+            Err(RomError::TruncatedChr {
+                expected: 0x2000,
+                found: 0x1FFF
+            })
+        ));
+
+        let mut archaic = raw.clone();
+        archaic[7] = 0x04;
+        assert!(matches!(
+            Rom::new(&archaic),
+            Err(RomError::UnsupportedHeader)
+        ));
+
+        let mut no_prg = header;
+        no_prg[4] = 0;
+        assert!(matches!(
+            Rom::new(&image(no_prg, 512, 0x2000)),
+            Err(RomError::MissingPrg)
+        ));
+
+        // huge NES 2.0 exponent-multiplier sizes must not overflow
+        let mut huge = header;
+        huge[7] = 0x08;
+        huge[4] = 0xFF;
+        huge[9] = 0xFF;
+        assert!(Rom::new(&image(huge, 0x100, 0)).is_err());
+
+        // every prefix of a valid image fails cleanly
+        for len in 0..raw.len() {
+            assert!(Rom::new(&raw[..len]).is_err());
+        }
+        assert!(Rom::new(&raw).is_ok());
+    }
+
+    #[test]
+    fn test_rom_from_reader() {
+        let file = std::fs::File::open("src/samples/Balloon Fight (USA).nes").unwrap();
+        let rom = Rom::from_reader(file).unwrap();
+        assert_eq!(rom.mapper, 0);
+
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+        let err = Rom::from_reader(Broken).err().unwrap();
+        assert!(matches!(err, RomError::Io(_)));
+        assert_eq!(err.to_string(), "cannot read ROM: disk on fire");
+
+        let unsupported = || {
+            let mut rom = Rom::from_reader(std::io::Cursor::new(
+                std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap(),
+            ))
+            .unwrap();
+            rom.mapper = 0x123;
+            rom
+        };
+        assert!(matches!(
+            mapper::from_rom(unsupported()),
+            Err(RomError::UnsupportedMapper(0x123))
+        ));
+        assert!(matches!(
+            Bus::new(unsupported(), |_, _| {}),
+            Err(RomError::UnsupportedMapper(0x123))
+        ));
+    }
+}
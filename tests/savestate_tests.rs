@@ -0,0 +1,144 @@
+#[cfg(test)]
+
+mod tests {
+    use nes::bus::Bus;
+    use nes::cpu::CPU;
+    use nes::rom::Rom;
+    use nes::savestate::rewind::Rewind;
+    use nes::savestate::{StateReader, StateWriter, OLDEST_VERSION, VERSION};
+
+    fn test_cpu() -> CPU<'static> {
+        let bytes: Vec<u8> = std::fs::read("src/samples/Balloon Fight (USA).nes").unwrap();
+        let rom = Rom::new(&bytes).unwrap();
+        let mut cpu = CPU::new(Bus::new(rom, |_, _| {}).unwrap());
+        cpu.reset();
+        cpu
+    }
+
+    fn run_frames(cpu: &mut CPU, frames: usize) {
+        let target = cpu.bus.ppu.frame_count() + frames;
+        while cpu.bus.ppu.frame_count() < target {
+            cpu.step();
+        }
+    }
+
+    #[test]
+    fn test_loaded_state_continues_identically() {
+        let mut cpu = test_cpu();
+        run_frames(&mut cpu, 30);
+        let state = cpu.save_state();
+
+        run_frames(&mut cpu, 10);
+
+        let mut restored = test_cpu();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        run_frames(&mut restored, 10);
+
+        assert_eq!(restored.pc, cpu.pc);
+        assert_eq!(restored.bus.cycles(), cpu.bus.cycles());
+        assert_eq!(restored.bus.ram, cpu.bus.ram);
+        assert_eq!(restored.bus.ppu.vram, cpu.bus.ppu.vram);
+        assert_eq!(restored.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_unsupported_states() {
+        let mut cpu = test_cpu();
+        let state = cpu.save_state();
+        run_frames(&mut cpu, 1);
+        let before = cpu.save_state();
+
+        assert!(cpu.load_state(b"NES\x1a").is_err());
+
+        let mut newer = state.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(cpu.load_state(&newer).is_err());
+
+        let mut older = state.clone();
+        older[4..6].copy_from_slice(&(OLDEST_VERSION - 1).to_le_bytes());
+        assert!(cpu.load_state(&older).is_err());
+
+        let truncated = &state[..state.len() - 1];
+        assert!(cpu.load_state(truncated).is_err());
+
+        assert_eq!(cpu.save_state(), before);
+    }
+
+    #[test]
+    fn test_unknown_chunks_and_missing_fields_are_tolerated() {
+        let mut cpu = test_cpu();
+        let mut state = cpu.save_state();
+
+        let mut extra = StateWriter::new();
+        extra.chunk(*b"NEW!", |state| state.u32(0xDEADBEEF));
+        state.extend_from_slice(&extra.finish()[6..]);
+        assert!(cpu.load_state(&state).is_ok());
+
+        // fields a chunk does not have yet read as zero
+        let mut reader = StateReader::new(&[0x34, 0x12]);
+        assert_eq!(reader.u16(), 0x1234);
+        assert_eq!(reader.u8(), 0);
+        assert!(!reader.bool());
+        let mut ram = [0xFF; 4];
+        reader.bytes_into(&mut ram);
+        assert_eq!(ram, [0; 4]);
+    }
+
+    #[test]
+    fn test_rewind_returns_frames_newest_first() {
+        let states: Vec<Vec<u8>> = (0..25u8)
+            .map(|frame| {
+                let mut state = vec![0; 2048];
+                state[100] = frame;
+                state[1500..1500 + frame as usize].fill(0xAA);
+                state
+            })
+            .collect();
+        let mut rewind = Rewind::with_keyframe_interval(100, 8);
+        for state in &states {
+            rewind.push(state);
+        }
+        assert_eq!(rewind.len(), 25);
+
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn test_rewind_is_bounded() {
+        let mut rewind = Rewind::with_keyframe_interval(20, 5);
+        for frame in 0..100u8 {
+            rewind.push(&[frame; 64]);
+        }
+        assert_eq!(rewind.len(), 20);
+
+        let frames: Vec<u8> = std::iter::from_fn(|| rewind.pop()).map(|s| s[0]).collect();
+        assert_eq!(frames, (80..100).rev().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_rewind_steps_the_machine_back() {
+        let mut cpu = test_cpu();
+        let mut rewind = Rewind::new(600);
+        let mut states = Vec::new();
+        for _ in 0..90 {
+            run_frames(&mut cpu, 1);
+            rewind.capture(&cpu);
+            states.push(cpu.save_state());
+        }
+
+        // deltas against the keyframe are much smaller than full states
+        assert!(rewind.memory_usage() < states.iter().map(Vec::len).sum::<usize>() / 8);
+
+        for state in states.iter().rev().take(40) {
+            assert!(rewind.step_back(&mut cpu));
+            assert_eq!(&cpu.save_state(), state);
+        }
+        run_frames(&mut cpu, 1);
+        assert_eq!(cpu.save_state(), states[51]);
+    }
+}